@@ -6,6 +6,9 @@ The `grel` terminal client.
 updated 2021-02-01
 */
 
+/* Explicit `return`s and `.len() == 0` are house style. */
+#![allow(clippy::needless_return, clippy::len_zero)]
+
 use lazy_static::lazy_static;
 use log::{error, debug, trace};
 use std::io::stdout;
@@ -16,14 +19,20 @@ use std::time::{Instant};
 //~ use termion::event::{Event, Key};
 //~ use termion::raw::IntoRawMode;
 
+use chrono::{DateTime, Local, TimeZone};
 use crossterm::{event, event::Event, event::KeyCode };
 
 //~ use grel::proto2::{Msg, Op};
-use grel::proto3::{Sndr, Rcvr, SndOp};
+use grel::proto3::{Sndr, Rcvr, SndOp, SndAdm, describe_age};
 use grel::sock::Sock;
-use grel::config::ClientConfig;
+use grel::config::{ClientConfig, Notify};
 use grel::line::Line;
 use grel::screen::Screen;
+use grel::history::History;
+use grel::complete::Completer;
+use grel::chatlog::ChatLog;
+use grel::user::ascollapse;
+use grel::matching::mentions;
 
 const JIFFY: std::time::Duration = std::time::Duration::from_millis(0);
 
@@ -35,13 +44,63 @@ lazy_static!{
 
 const SPACE:    char = ' ';
 const RETURN:   char = '\n';
-const OP_ERROR: &str = "# The recognized OP subcommands are OPEN, CLOSE, KICK, INVITE, and GIVE.";
+const ADMIN_ERROR: &str = "# The recognized ADMIN subcommands are AUTH NAME PASSWORD, KILL USER [REASON], BAN USER [REASON], UNBAN ADDRESS, BANS, CLOSE ROOM, DESTROY ROOM [REASON], RENAME USER NEW NAME, OWNER ROOM USER, BROADCAST MESSAGE, SHUTDOWN SECONDS [REASON], CANCEL, and RELOAD.";
+/* Commands (and subcommands) offered by Tab completion. */
+const COMMANDS: &[&str] = &[
+    "quit", "priv", "memo", "name", "join", "who", "rooms", "roominfo", "whois",
+    "block", "unblock", "away", "register", "identify", "op", "admin", "window",
+    "query",
+];
+const OP_COMMANDS: &[&str] = &[
+    "open", "close", "key", "unkey", "topic", "limit", "slow", "kick", "ban",
+    "unban", "bans", "mute", "unmute", "invite", "uninvite", "invites", "oper",
+    "mod", "demote", "give",
+];
+const ADMIN_COMMANDS: &[&str] = &[
+    "auth", "kill", "ban", "unban", "bans", "close", "destroy", "rename", "owner",
+    "broadcast", "shutdown", "cancel", "reload",
+];
+const ROOM_COMMANDS: &[&str] = &["join", "rooms", "roominfo"];
+const OP_ERROR: &str = "# The recognized OP subcommands are OPEN, CLOSE, KEY, UNKEY, TOPIC, LIMIT, SLOW, KICK, BAN, UNBAN, BANS, MUTE, UNMUTE, INVITE, UNINVITE, INVITES, OPER, MOD, DEMOTE, and GIVE.";
 
 /** Represents the vaguely vi-like mode the client is in. */
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum Mode {
     Command,
     Input,
+    /// typing a scrollback search pattern (after `/` or `?`)
+    Search,
+}
+
+/** State of an in-progress reverse search through the input history. */
+struct Search {
+    pattern: String,
+    /// index of the current match in the `History`
+    found: Option<usize>,
+    /// whether the last attempt to extend the search failed
+    failing: bool,
+    /// what was on the input line when the search started
+    saved: String,
+}
+
+/** What a window (one of the `Screen`'s buffers) shows. */
+#[derive(Clone, Debug, PartialEq)]
+enum Target {
+    /// server announcements, memos, &c.
+    Status,
+    /// chat in a Room, by collapsed name
+    Room(String),
+    /// a private conversation, by the other user's collapsed name
+    Query(String),
+}
+
+/** A search of the scrollback, either being typed or already made. */
+struct Find {
+    pattern: String,
+    /// searching toward older lines (started with `?`)
+    backward: bool,
+    /// whether the last search turned up nothing
+    failing: bool,
 }
 
 /** Global variable struct. */
@@ -55,6 +114,20 @@ struct Globals {
     socket: Sock,
     cmd: char,
     run: bool,
+    away: bool,
+    history: History,
+    search: Option<Search>,
+    find: Option<Find>,
+    find_prompt: Option<Find>,
+    completer: Completer,
+    /// what each of the `Screen`'s buffers shows, by index
+    windows: Vec<Target>,
+    /// words (collapsed) that count as mentions, besides `uname`
+    highlights: Vec<String>,
+    notify: Notify,
+    /// format of scrollback timestamps, if they're shown
+    timestamp: Option<String>,
+    chatlog: Option<ChatLog>,
 }
 
 impl Globals {
@@ -139,19 +212,18 @@ fn connect(cfg: &ClientConfig) -> Result<Sock, String> {
     };
     let b = Sndr::Name(&cfg.name).bytes();
     let res = thesock.blocking_send(&b, cfg.tick);
-    match res {
-        Err(e) => match thesock.shutdown() {
+    if let Err(e) = res {
+        match thesock.shutdown() {
             Err(ee) => { return Err(format!("Error in initial protocol: {}; error during shutdown: {}", e, ee)); },
             Ok(()) =>  { return Err(format!("Error in initial protocol: {}", e)); },
-        },
-        Ok(()) => {},
+        }
     }
     
     return Ok(thesock);
 }
 
 /** Divide &str s into alternating chunks of whitespace and non-whitespace. */
-fn tokenize_the_whitespace_too<'a>(s: &'a str) -> Vec<&'a str> {
+fn tokenize_the_whitespace_too(s: &str) -> Vec<&str> {
     let mut v: Vec<&str> = Vec::new();
     
     let mut change: usize = 0;
@@ -198,11 +270,11 @@ fn split_command_toks<'a>(toks: &'a [&str], n_cmds: usize)
     let mut n: usize = 0;
     for _ in 0..n_cmds {
         cmds.push(toks[n]);
-        n = n + 2;
+        n += 2;
     }
     while n < toks.len() {
         arg.push_str(toks[n]);
-        n = n + 1;
+        n += 1;
     }
     
     return Ok((cmds, arg));
@@ -221,7 +293,7 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
             
             /* Collect the ipt vector as a string, discarding the cmd_char and
             translating newlines to spaces. */
-            let cmd_line: String = ipt[1..].into_iter()
+            let cmd_line: String = ipt[1..].iter()
                 .map(|c| if *c == RETURN { SPACE } else { *c }).collect();
                 
             /* Tokenize the resulting string. */
@@ -252,6 +324,22 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
                     }
                 },
                 
+                "memo" => {
+                    match split_command_toks(&cmd_toks, 2) {
+                        Ok((cmds, arg)) => {
+                            gv.enqueue(&Sndr::Memo {
+                                who: cmds[1],
+                                text: &arg,
+                            });
+                        },
+                        Err(_) => {
+                            let mut sl = Line::new();
+                            sl.pushf("# You must specify a recipient for a memo.", &scrn.styles().dim);
+                            scrn.push_line(sl);
+                        },
+                    }
+                },
+                
                 "name" => {
                     match split_command_toks(&cmd_toks, 1) {
                         Ok((_, arg)) => { gv.enqueue(&Sndr::Name(&arg)); },
@@ -260,9 +348,26 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
                 },
                 
                 "join" => {
-                    match split_command_toks(&cmd_toks, 1) {
-                        Ok((_, arg)) => { gv.enqueue(&Sndr::Join(&arg)); },
-                        Err(_) => { return; },
+                    /* ;join -k KEY Room Name */
+                    if cmd_toks.len() > 2 && cmd_toks[2] == "-k" {
+                        match split_command_toks(&cmd_toks, 3) {
+                            Ok((cmds, arg)) if arg.len() > 0 => {
+                                gv.enqueue(&Sndr::JoinKey {
+                                    room: &arg,
+                                    key: cmds[2],
+                                });
+                            },
+                            _ => {
+                                let mut sl = Line::new();
+                                sl.pushf("# Usage: ;join -k KEY Room Name", &scrn.styles().dim);
+                                scrn.push_line(sl);
+                            },
+                        }
+                    } else {
+                        match split_command_toks(&cmd_toks, 1) {
+                            Ok((_, arg)) => { gv.enqueue(&Sndr::Join(&arg)); },
+                            Err(_) => { return; },
+                        }
                     }
                 },
                 
@@ -278,6 +383,58 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
                     }
                 },
                 
+                "roominfo" => {
+                    match split_command_toks(&cmd_toks, 1) {
+                        Ok((_, arg)) => {
+                            gv.enqueue(&Sndr::Query{
+                                what: "room_info",
+                                arg: &arg,
+                            });
+                        },
+                        Err(_) => { return; }
+                    }
+                },
+                
+                "whois" => {
+                    match split_command_toks(&cmd_toks, 1) {
+                        Ok((_, arg)) => {
+                            gv.enqueue(&Sndr::Query{
+                                what: "whois",
+                                arg: &arg,
+                            });
+                        },
+                        Err(_) => { return; }
+                    }
+                },
+                
+                "query" => {
+                    match split_command_toks(&cmd_toks, 2) {
+                        Ok((cmds, arg)) => {
+                            let n = window_for(scrn, gv, Target::Query(ascollapse(cmds[1])), cmds[1]);
+                            switch_window(scrn, n);
+                            if arg.len() > 0 {
+                                gv.enqueue(&Sndr::Priv {
+                                    who: cmds[1],
+                                    text: &arg,
+                                });
+                            }
+                        },
+                        Err(_) => {
+                            let mut sl = Line::new();
+                            sl.pushf("# You must specify whom to talk to.", &scrn.styles().dim);
+                            scrn.push_line(sl);
+                        },
+                    }
+                },
+                
+                "window" => {
+                    let arg = match split_command_toks(&cmd_toks, 1) {
+                        Ok((_, arg)) => arg,
+                        Err(_) => String::new(),
+                    };
+                    window_command(arg.trim(), scrn, gv);
+                },
+                
                 "block" => {
                     match split_command_toks(&cmd_toks, 1) {
                         Ok((_, arg)) => { gv.enqueue(&Sndr::Block(&arg)); },
@@ -292,6 +449,47 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
                     }
                 },
                 
+                "away" => {
+                    /* ;away with no message means "back" */
+                    match split_command_toks(&cmd_toks, 1) {
+                        Ok((_, arg)) => { gv.enqueue(&Sndr::Away(&arg)); },
+                        Err(_) => { return; },
+                    }
+                },
+                
+                "register" => {
+                    match split_command_toks(&cmd_toks, 2) {
+                        Ok((cmds, _)) => { gv.enqueue(&Sndr::Register(cmds[1])); },
+                        Err(_) => {
+                            let mut sl = Line::new();
+                            sl.pushf("# Usage: ;register PASSWORD", &scrn.styles().dim);
+                            scrn.push_line(sl);
+                        },
+                    }
+                },
+                
+                "identify" => {
+                    /* ;identify PASSWORD [Registered Name] */
+                    match split_command_toks(&cmd_toks, 2) {
+                        Ok((cmds, arg)) => {
+                            let name = if arg.trim().len() > 0 {
+                                arg
+                            } else {
+                                gv.uname.clone()
+                            };
+                            gv.enqueue(&Sndr::Identify {
+                                name: &name,
+                                password: cmds[1],
+                            });
+                        },
+                        Err(_) => {
+                            let mut sl = Line::new();
+                            sl.pushf("# Usage: ;identify PASSWORD [name]", &scrn.styles().dim);
+                            scrn.push_line(sl);
+                        },
+                    }
+                },
+                
                 "op" => {
                     match split_command_toks(&cmd_toks, 2) {
                         Err(_) => {
@@ -303,8 +501,35 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
                             let msg: Option<Sndr> = match cmds[1].to_lowercase().as_str() {
                                 "open"   => Some(Sndr::Op(SndOp::Open)),
                                 "close"  => Some(Sndr::Op(SndOp::Close)),
-                                "ban" | "kick" => Some(Sndr::Op(SndOp::Kick(&arg))),
+                                "key"    => Some(Sndr::Op(SndOp::Key(&arg))),
+                                "unkey"  => Some(Sndr::Op(SndOp::Unkey)),
+                                "topic"  => Some(Sndr::Op(SndOp::Topic(&arg))),
+                                "limit" | "slow" => match arg.trim().parse::<u32>() {
+                                    Ok(n) => if cmds[1].eq_ignore_ascii_case("limit") {
+                                        Some(Sndr::Op(SndOp::Limit(n)))
+                                    } else {
+                                        Some(Sndr::Op(SndOp::Slow(n)))
+                                    },
+                                    Err(_) => {
+                                        let mut sl = Line::new();
+                                        sl.pushf("# LIMIT and SLOW take a number (0 to turn them off).",
+                                                 &scrn.styles().dim);
+                                        scrn.push_line(sl);
+                                        None
+                                    },
+                                },
+                                "kick"   => Some(Sndr::Op(SndOp::Kick(&arg))),
+                                "ban"    => Some(Sndr::Op(SndOp::Ban(&arg))),
+                                "unban"  => Some(Sndr::Op(SndOp::Unban(&arg))),
+                                "bans"   => Some(Sndr::Op(SndOp::Bans)),
+                                "mute"   => Some(Sndr::Op(SndOp::Mute(&arg))),
+                                "unmute" => Some(Sndr::Op(SndOp::Unmute(&arg))),
                                 "invite" => Some(Sndr::Op(SndOp::Invite(&arg))),
+                                "uninvite" => Some(Sndr::Op(SndOp::Uninvite(&arg))),
+                                "invites"  => Some(Sndr::Op(SndOp::Invites)),
+                                "oper"   => Some(Sndr::Op(SndOp::Operator(&arg))),
+                                "mod"    => Some(Sndr::Op(SndOp::Moderator(&arg))),
+                                "demote" => Some(Sndr::Op(SndOp::Demote(&arg))),
                                 "give"   => Some(Sndr::Op(SndOp::Give(&arg))),
                                 _ => {
                                     let mut sl = Line::new();
@@ -318,7 +543,53 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
                     }
                 },
                 
-                x @ _ => {
+                "admin" => {
+                    let sub = match split_command_toks(&cmd_toks, 2) {
+                        Ok((cmds, _)) => cmds[1].to_lowercase(),
+                        Err(_) => String::new(),
+                    };
+                    /* Most subcommands take one single-word argument (a user
+                    or room name) and then the rest of the line; the rest
+                    take just the rest of the line. */
+                    let n_cmds = match sub.as_str() {
+                        "auth" | "kill" | "ban" | "destroy" | "rename" | "owner"
+                            | "shutdown" => 3,
+                        _ => 2,
+                    };
+                    let sent = match split_command_toks(&cmd_toks, n_cmds) {
+                        Err(_) => false,
+                        Ok((cmds, arg)) => {
+                            let msg: Option<Sndr> = match sub.as_str() {
+                                "auth"    => Some(Sndr::Admin(SndAdm::Auth { name: cmds[2], password: &arg })),
+                                "kill"    => Some(Sndr::Admin(SndAdm::Kill { who: cmds[2], why: &arg })),
+                                "ban"     => Some(Sndr::Admin(SndAdm::Ban { who: cmds[2], why: &arg })),
+                                "unban"   => Some(Sndr::Admin(SndAdm::Unban(&arg))),
+                                "bans"    => Some(Sndr::Admin(SndAdm::Bans)),
+                                "close"   => Some(Sndr::Admin(SndAdm::Close(&arg))),
+                                "destroy" => Some(Sndr::Admin(SndAdm::Destroy { room: cmds[2], why: &arg })),
+                                "rename"  => Some(Sndr::Admin(SndAdm::Rename { who: cmds[2], name: &arg })),
+                                "owner"   => Some(Sndr::Admin(SndAdm::Owner { room: cmds[2], who: &arg })),
+                                "broadcast" => Some(Sndr::Admin(SndAdm::Broadcast(&arg))),
+                                "shutdown" => match cmds[2].parse::<u32>() {
+                                    Ok(secs) => Some(Sndr::Admin(SndAdm::Shutdown { secs, why: &arg })),
+                                    Err(_) => None,
+                                },
+                                "cancel"  => Some(Sndr::Admin(SndAdm::Cancel)),
+                                "reload"  => Some(Sndr::Admin(SndAdm::Reload)),
+                                _ => None,
+                            };
+                            if let Some(m) = &msg { gv.enqueue(m); }
+                            msg.is_some()
+                        },
+                    };
+                    if !sent {
+                        let mut sl = Line::new();
+                        sl.pushf(ADMIN_ERROR, &scrn.styles().dim);
+                        scrn.push_line(sl);
+                    }
+                },
+                
+                x => {
                     let mut sl = Line::new();
                     sl.pushf("# Unknown command ", &scrn.styles().dim);
                     sl.pushf(x, &scrn.styles().dim_bold);
@@ -340,6 +611,20 @@ fn respond_to_user_input(ipt: Vec<char>, scrn: &mut Screen, gv: &mut Globals) {
         }
     }
     lines.push(cur_line);
+    
+    /* In a private conversation's window, plain input goes to the other
+    user, a line at a time. */
+    if let Target::Query(_) = &gv.windows[scrn.current_buffer()] {
+        let who = scrn.buffer_name(scrn.current_buffer()).to_string();
+        for line in lines.iter() {
+            gv.enqueue(&Sndr::Priv {
+                who: &who,
+                text: line,
+            });
+        }
+        return;
+    }
+    
     let lineref: Vec<&str> = lines.iter().map(|x| x.as_str()).collect();
     gv.enqueue(&Sndr::Text {
         who: "",
@@ -353,6 +638,28 @@ fn command_key(evt: event::KeyEvent, scrn: &mut Screen, gv: &mut Globals) {
         KeyCode::Char(SPACE) | KeyCode::Enter => {
             gv.mode = Mode::Input;
         },
+        KeyCode::Char(c @ '/') | KeyCode::Char(c @ '?') => {
+            gv.find_prompt = Some(Find {
+                pattern: String::new(),
+                backward: c == '?',
+                failing: false,
+            });
+            gv.mode = Mode::Search;
+        },
+        /* `n` repeats the last search in the same direction; `N` in the
+        opposite one. */
+        KeyCode::Char(c @ 'n') | KeyCode::Char(c @ 'N') => {
+            if let Some(f) = gv.find.as_mut() {
+                let backward = f.backward != (c == 'N');
+                f.failing = !scrn.search_lines(backward);
+                write_mode_line(scrn, gv);
+            }
+        },
+        KeyCode::Esc if gv.find.is_some() => {
+            gv.find = None;
+            scrn.set_search(None);
+            write_mode_line(scrn, gv);
+        },
         /* The values passed to the two scrolling functions are opposite
         because the roster scroll is counted from the top, but the main
         window scroll is counted from the bottom.
@@ -399,13 +706,130 @@ fn command_key(evt: event::KeyEvent, scrn: &mut Screen, gv: &mut Globals) {
     }
 }
 
+/* Return whether an input line holds a password or key, and so shouldn't
+be remembered in the input history (or have its keystrokes logged).
+*/
+fn is_secret(line: &str, cmd_char: char) -> bool {
+    let line = match line.strip_prefix(cmd_char) {
+        Some(x) => x.to_lowercase(),
+        None => { return false; },
+    };
+    let toks: Vec<&str> = line.split_whitespace().take(2).collect();
+    return matches!(toks.as_slice(),
+        ["register", ..] | ["identify", ..]
+        | ["admin", "auth"] | ["op", "key"] | ["join", "-k"]);
+}
+
+/** Respond to keypress events during a reverse search of the input history
+(started with ctrl-R in _input_ mode).
+
+Returns `false` if the search has ended and the key should also be handled
+as it normally would be (so, for example, `Enter` sends the line found).
+*/
+fn search_key(evt: event::KeyEvent, scrn: &mut Screen, gv: &mut Globals) -> bool {
+    let ctrl = evt.modifiers.contains(event::KeyModifiers::CONTROL);
+    let cancel = evt.code == KeyCode::Esc || (ctrl && evt.code == KeyCode::Char('g'));
+    let srch = match gv.search.as_mut() {
+        Some(s) => s,
+        None => { return false; },
+    };
+    let everything = gv.history.len();
+    
+    match evt.code {
+        /* Give up, restoring the input line to what it was. */
+        _ if cancel => {
+            scrn.set_input(&srch.saved);
+            gv.search = None;
+            write_mode_line(scrn, gv);
+            return true;
+        },
+        /* Look for the next older match. */
+        KeyCode::Char('r') if ctrl => {
+            if srch.pattern.len() > 0 {
+                let before = srch.found.unwrap_or(everything);
+                match gv.history.search(&srch.pattern, before) {
+                    Some(n) => { srch.found = Some(n); srch.failing = false; },
+                    None => { srch.failing = true; },
+                }
+            }
+        },
+        KeyCode::Backspace => {
+            srch.pattern.pop();
+            srch.found = match srch.pattern.len() {
+                0 => None,
+                _ => gv.history.search(&srch.pattern, everything),
+            };
+            srch.failing = srch.pattern.len() > 0 && srch.found.is_none();
+        },
+        /* Extending the pattern keeps the current match if it still
+        matches; otherwise it looks further back. */
+        KeyCode::Char(c) if !ctrl => {
+            srch.pattern.push(c);
+            let before = srch.found.map(|n| n + 1).unwrap_or(everything);
+            match gv.history.search(&srch.pattern, before) {
+                Some(n) => { srch.found = Some(n); srch.failing = false; },
+                None => { srch.failing = true; },
+            }
+        },
+        _ => {
+            gv.search = None;
+            write_mode_line(scrn, gv);
+            return false;
+        },
+    }
+    
+    let hist = &gv.history;
+    match srch.found.and_then(|n| hist.get(n)) {
+        Some(line) => scrn.set_input(line),
+        None => scrn.set_input(&srch.saved),
+    }
+    write_mode_line(scrn, gv);
+    return true;
+}
+
 /** Respond to keypress events in _input_ mode. */
 fn input_key(evt: event::KeyEvent, scrn: &mut Screen, gv: &mut Globals) {
+    if gv.search.is_some() && search_key(evt, scrn, gv) { return; }
+    
     match evt.code {
         KeyCode::Enter => {
             let cv = scrn.pop_input();
+            let line: String = cv.iter().collect();
+            if is_secret(&line, gv.cmd) {
+                gv.history.reset();
+            } else {
+                gv.history.push(&line);
+            }
             respond_to_user_input(cv, scrn, gv);
         },
+        KeyCode::Up => {
+            let current: String = scrn.get_input().iter().collect();
+            if let Some(line) = gv.history.older(&current) {
+                scrn.set_input(line);
+            }
+        },
+        KeyCode::Down => {
+            if let Some(line) = gv.history.newer() {
+                scrn.set_input(line);
+            }
+        },
+        KeyCode::Tab => {
+            let ip = scrn.get_input_ip();
+            if let Some((line, new_ip)) = gv.completer.complete(scrn.get_input(), ip) {
+                let s: String = line.iter().collect();
+                scrn.set_input(&s);
+                scrn.input_skip_chars(new_ip as i16 - line.len() as i16);
+            }
+        },
+        KeyCode::Char('r') if evt.modifiers.contains(event::KeyModifiers::CONTROL) => {
+            gv.search = Some(Search {
+                pattern: String::new(),
+                found: None,
+                failing: false,
+                saved: scrn.get_input().iter().collect(),
+            });
+            write_mode_line(scrn, gv);
+        },
         KeyCode::Backspace => {
             if scrn.get_input_length() == 0 {
                 gv.mode = Mode::Command;
@@ -446,15 +870,54 @@ fn input_key(evt: event::KeyEvent, scrn: &mut Screen, gv: &mut Globals) {
         },
         KeyCode::Esc    => { gv.mode = Mode::Command; },
         KeyCode::Char('\u{1b}') => {
-            if evt.modifiers.contains(event::KeyModifiers::ALT) {
-                gv.mode = Mode::Command;
-            }
+            let alt = evt.modifiers.contains(event::KeyModifiers::ALT);
+            if alt { gv.mode = Mode::Command; }
         },
         KeyCode::Char(c) => { scrn.input_char(c); },
         _ => { /* */ }
     }
 }
 
+/** Respond to keypress events while typing a scrollback search pattern. */
+fn search_prompt_key(evt: event::KeyEvent, scrn: &mut Screen, gv: &mut Globals) {
+    let prompt = match gv.find_prompt.as_mut() {
+        Some(f) => f,
+        None => { gv.mode = Mode::Command; return; },
+    };
+    
+    match evt.code {
+        KeyCode::Enter => {
+            let mut f = gv.find_prompt.take().unwrap();
+            /* Like vi, an empty pattern means the last one again. */
+            if f.pattern.len() == 0 {
+                match &gv.find {
+                    Some(old) => { f.pattern = old.pattern.clone(); },
+                    None => { gv.mode = Mode::Command; return; },
+                }
+            }
+            let same = gv.find.as_ref().is_some_and(|old| old.pattern == f.pattern);
+            if !same { scrn.set_search(Some(&f.pattern)); }
+            f.failing = !scrn.search_lines(f.backward);
+            gv.find = Some(f);
+            gv.mode = Mode::Command;
+        },
+        KeyCode::Esc => {
+            gv.find_prompt = None;
+            gv.mode = Mode::Command;
+        },
+        KeyCode::Backspace => {
+            let popped = prompt.pattern.pop();
+            if popped.is_none() {
+                gv.find_prompt = None;
+                gv.mode = Mode::Command;
+            }
+        },
+        KeyCode::Char(c) => { prompt.pattern.push(c); },
+        _ => { /* */ },
+    }
+    write_mode_line(scrn, gv);
+}
+
 /** While the terminal polls that events are available, read them and
 act accordingly.
 
@@ -471,11 +934,20 @@ fn process_user_typing(
         let cur_mode = gv.mode;
         
         match event::read()? {
+            /* alt-1 through alt-9 switch windows in any mode. */
+            Event::Key(event::KeyEvent { code: KeyCode::Char(c @ '1'..='9'), modifiers })
+            if modifiers.contains(event::KeyModifiers::ALT) => {
+                let n = (c as usize) - ('1' as usize);
+                switch_window(scrn, n);
+            },
             Event::Key(evt) => {
-                trace!("event: {:?}", evt);
+                /* Keep typed passwords and keys out of the log. */
+                let line: String = scrn.get_input().iter().collect();
+                if !is_secret(&line, gv.cmd) { trace!("event: {:?}", evt); }
                 match gv.mode {
                     Mode::Command => command_key(evt, scrn, gv),
                     Mode::Input   => input_key(evt, scrn, gv),
+                    Mode::Search  => search_prompt_key(evt, scrn, gv),
                 }
             },
             Event::Resize(w, h) => scrn.resize(w, h),
@@ -502,22 +974,38 @@ fn process_msg(m: Rcvr,
         Rcvr::Ping => { gv.socket.enqueue(&PING); },
         
         Rcvr::Text { who, lines } => {
+            gv.completer.saw_name(&who);
             for lin in &lines {
+                let mention = who != gv.uname && mentions_me(gv, lin);
                 let mut sl = Line::new();
-                sl.pushf(&who, &scrn.styles().high);
+                sl.pushf(&who, scrn.styles().nick(&who));
                 sl.push(": ");
-                sl.push(lin);
-                scrn.push_line(sl);
+                sl.set_indent(sl.len());
+                if mention {
+                    sl.pushf(lin, &scrn.styles().mention);
+                    notify(scrn, gv, &who, lin);
+                } else if who == gv.uname {
+                    sl.pushf(lin, &scrn.styles().own);
+                } else {
+                    sl.pushf(lin, &scrn.styles().others);
+                }
+                push_room(scrn, gv, sl, mention);
             }
         },
         
         Rcvr::Priv { who, text } => {
+            gv.completer.saw_name(&who);
             let mut sl = Line::new();
-            sl.push("$ ");
-            sl.pushf(&who, &scrn.styles().dim);
+            sl.pushf(&who, scrn.styles().nick(&who));
             sl.push(": ");
-            sl.push(&text);
-            scrn.push_line(sl);
+            sl.set_indent(sl.len());
+            if mentions_me(gv, &text) {
+                sl.pushf(&text, &scrn.styles().mention);
+            } else {
+                sl.pushf(&text, &scrn.styles().private);
+            }
+            notify(scrn, gv, &who, &text);
+            push_query(scrn, gv, &who, sl, true);
         },
         
         Rcvr::Logout(s) => {
@@ -527,16 +1015,16 @@ fn process_msg(m: Rcvr,
         
         Rcvr::Info(s) => {
             let mut sl = Line::new();
-            sl.push("* ");
-            sl.push(&s);
-            scrn.push_line(sl);
+            sl.pushf("* ", &scrn.styles().info);
+            sl.pushf(&s, &scrn.styles().info);
+            push_here(scrn, gv, sl);
         },
 
         Rcvr::Err(s) => {
             let mut sl = Line::new();
-            sl.pushf("# ", &scrn.styles().dim);
-            sl.pushf(&s, &scrn.styles().dim);
-            scrn.push_line(sl);
+            sl.pushf("# ", &scrn.styles().error);
+            sl.pushf(&s, &scrn.styles().error);
+            push_here(scrn, gv, sl);
         },
         
         Rcvr::Misc { ref what, ref alt, ref data, } => match what.as_str() {
@@ -545,6 +1033,8 @@ fn process_msg(m: Rcvr,
                     [x, y] => (x, y),
                     _ => { return Err(format!("Incomplete data: {:?}", &m)); },
                 };
+                gv.completer.saw_name(name);
+                gv.completer.saw_room(room);
                 let mut sl = Line::new();
                 sl.push("* ");
                 if name.as_str() == gv.uname.as_str() {
@@ -554,8 +1044,12 @@ fn process_msg(m: Rcvr,
                     /* Set room name in upper-right status line. */
                     gv.rname = room.to_string();
                     let mut room_line = Line::new();
-                    room_line.pushf(&gv.rname, &scrn.styles().high);
+                    room_line.pushf(&gv.rname, &scrn.styles().status);
                     scrn.set_stat_ur(room_line);
+                    
+                    /* Show the new Room's window. */
+                    let n = room_window(scrn, gv);
+                    switch_window(scrn, n);
                 } else {
                     sl.pushf(name, &scrn.styles().high);
                     sl.push(" joins ");
@@ -563,7 +1057,7 @@ fn process_msg(m: Rcvr,
                 sl.pushf(room, &scrn.styles().high);
                 sl.push(".");
                 gv.enqueue_bytes(&ROSTER_REQUEST);
-                scrn.push_line(sl);
+                push_room(scrn, gv, sl, false);
             },
             
             "leave" => {
@@ -577,7 +1071,7 @@ fn process_msg(m: Rcvr,
                 sl.push(" leaves: ");
                 sl.push(message);
                 gv.enqueue_bytes(&ROSTER_REQUEST);
-                scrn.push_line(sl);
+                push_room(scrn, gv, sl, false);
             },
             
             "priv_echo" => {
@@ -586,13 +1080,29 @@ fn process_msg(m: Rcvr,
                     _ => { return Err(format!("Incomplete data: {:?}", &m)); }
                 };
                 let mut sl = Line::new();
+                sl.pushf(&gv.uname, scrn.styles().nick(&gv.uname));
+                sl.push(": ");
+                sl.set_indent(sl.len());
+                sl.pushf(text, &scrn.styles().private);
+                push_query(scrn, gv, name, sl, false);
+            },
+            
+            "memo" => {
+                let (name, sent, text) = match &data[..] {
+                    [x, y, z] => (x, y, z),
+                    _ => { return Err(format!("Incomplete data: {:?}", &m)); }
+                };
+                let age = match sent.parse::<u64>() {
+                    Ok(n) => describe_age(n),
+                    Err(_) => String::from("at an unknown time"),
+                };
+                let mut sl = Line::new();
                 sl.push("$ ");
-                sl.pushf("You", &scrn.styles().dim_bold);
-                sl.pushf(" @ ", &scrn.styles().dim);
-                sl.pushf(&name, &scrn.styles().high);
+                sl.pushf(name, &scrn.styles().dim);
+                sl.pushf(format!(" [memo, {}]", &age), &scrn.styles().dim);
                 sl.push(": ");
-                sl.push(&text);
-                scrn.push_line(sl);
+                sl.push(text);
+                push_to_at(scrn, gv, 0, sl, true, false, sent.parse::<u64>().ok());
             },
             
             "name" => {
@@ -600,6 +1110,7 @@ fn process_msg(m: Rcvr,
                     [x, y] => (x, y),
                     _ => { return Err(format!("Incomplete data: {:?}", &m)); },
                 };
+                gv.completer.saw_name(new);
                 
                 let mut sl = Line::new();
                 sl.push("* ");
@@ -614,7 +1125,16 @@ fn process_msg(m: Rcvr,
                 }
                 sl.pushf(new, &scrn.styles().high);
                 sl.push(".");
-                scrn.push_line(sl);
+                
+                /* A private conversation follows its user to the new name. */
+                let old_target = Target::Query(ascollapse(old));
+                if let Some(n) = gv.windows.iter().position(|t| t == &old_target) {
+                    gv.windows[n] = Target::Query(ascollapse(new));
+                    scrn.set_buffer_name(n, new);
+                    push_to(scrn, gv, n, sl.clone(), false);
+                    write_window_line(scrn);
+                }
+                push_room(scrn, gv, sl, false);
                 gv.enqueue_bytes(&ROSTER_REQUEST);
             },
             
@@ -628,20 +1148,92 @@ fn process_msg(m: Rcvr,
                 sl.push("* ");
                 if name == &gv.uname {
                     sl.pushf("You", &scrn.styles().bold);
-                    sl.push(" are now the operator of ");
+                    sl.push(" are now the owner of ");
                 } else {
                     sl.pushf(name, &scrn.styles().high);
-                    sl.push(" is now the operator of ");
+                    sl.push(" is now the owner of ");
                 }
                 sl.pushf(room, &scrn.styles().bold);
                 sl.push(".");
-                scrn.push_line(sl);
+                push_room(scrn, gv, sl, false);
                 gv.enqueue_bytes(&ROSTER_REQUEST);
             }
             
+            "role" => {
+                let (name, role, room) = match &data[..] {
+                    [x, y, z] => (x, y, z),
+                    _ => { return Err(format!("Incomplete data: {:?}", &m)); },
+                };
+                
+                let mut sl = Line::new();
+                sl.push("* ");
+                if name == &gv.uname {
+                    sl.pushf("You", &scrn.styles().bold);
+                    sl.push(" are now a ");
+                } else {
+                    sl.pushf(name, &scrn.styles().high);
+                    sl.push(" is now a ");
+                }
+                sl.pushf(role, &scrn.styles().bold);
+                sl.push(" of ");
+                sl.pushf(room, &scrn.styles().high);
+                sl.push(".");
+                push_room(scrn, gv, sl, false);
+                gv.enqueue_bytes(&ROSTER_REQUEST);
+            },
+            
             "roster" => {
-                if data.len() < 1 { return Err(format!("Incomplete data: {:?}", &m)); }
-                scrn.set_roster(data);
+                if data.len() < 3 || data.len() % 3 != 0 {
+                    return Err(format!("Incomplete data: {:?}", &m));
+                }
+                let mut rost: Vec<Line> = Vec::with_capacity(data.len() / 3);
+                gv.completer.set_roster(data.chunks(3).map(|t| t[1].clone()).collect());
+                for trip in data.chunks(3) {
+                    let mut l = Line::new();
+                    l.pushf(&trip[0], &scrn.styles().op);
+                    if trip[2].len() > 0 {
+                        l.pushf(&trip[1], &scrn.styles().dim);
+                        l.pushf(" (away)", &scrn.styles().dim);
+                    } else {
+                        l.push(&trip[1]);
+                    }
+                    rost.push(l);
+                }
+                scrn.set_roster(rost);
+            },
+            
+            "away" => {
+                let (name, msg) = match &data[..] {
+                    [x, y] => (x, y),
+                    _ => { return Err(format!("Incomplete data: {:?}", &m)); },
+                };
+                let mut sl = Line::new();
+                sl.push("* ");
+                if name == &gv.uname {
+                    gv.away = msg.len() > 0;
+                    write_mode_line(scrn, gv);
+                    sl.pushf("You", &scrn.styles().bold);
+                    sl.push(if msg.len() > 0 { " are away: " } else { " are back." });
+                } else {
+                    sl.pushf(name, &scrn.styles().high);
+                    sl.push(if msg.len() > 0 { " is away: " } else { " is back." });
+                }
+                sl.push(msg);
+                push_room(scrn, gv, sl, false);
+                gv.enqueue_bytes(&ROSTER_REQUEST);
+            },
+            
+            "away_reply" => {
+                let (name, msg) = match &data[..] {
+                    [x, y] => (x, y),
+                    _ => { return Err(format!("Incomplete data: {:?}", &m)); },
+                };
+                let mut sl = Line::new();
+                sl.push("$ ");
+                sl.pushf(name, &scrn.styles().high);
+                sl.pushf(" is away: ", &scrn.styles().dim);
+                sl.push(msg);
+                push_query(scrn, gv, name, sl, false);
             },
             
             "kick_other" => {
@@ -655,7 +1247,7 @@ fn process_msg(m: Rcvr,
                 sl.push(" has been kicked from ");
                 sl.pushf(room, &scrn.styles().high);
                 sl.push(".");
-                scrn.push_line(sl);
+                push_room(scrn, gv, sl, false);
                 gv.enqueue_bytes(&ROSTER_REQUEST);
             },
 
@@ -670,11 +1262,149 @@ fn process_msg(m: Rcvr,
                 sl.push(" have been kicked from ");
                 sl.pushf(room, &scrn.styles().high);
                 sl.push(".");
-                scrn.push_line(sl);
+                push_room(scrn, gv, sl, false);
+            },
+            
+            "bans" | "invites" => {
+                if data.len() < 4 || (data.len() - 1) % 3 != 0 {
+                    return Err(format!("Incomplete data: {:?}", &m));
+                }
+                let mut sl = Line::new();
+                match what.as_str() {
+                    "bans" => { sl.push("* Banned from "); },
+                    _      => { sl.push("* Invited to "); },
+                }
+                sl.pushf(&data[0], &scrn.styles().high);
+                sl.push(":");
+                push_here(scrn, gv, sl);
+                for trip in data[1..].chunks(3) {
+                    let age = match trip[2].parse::<u64>() {
+                        Ok(n) => describe_age(n),
+                        Err(_) => String::from("at an unknown time"),
+                    };
+                    let mut sl = Line::new();
+                    sl.push("*   ");
+                    sl.pushf(&trip[0], &scrn.styles().high);
+                    sl.pushf(format!(" (by {}, {})", &trip[1], &age), &scrn.styles().dim);
+                    push_here(scrn, gv, sl);
+                }
+            },
+            
+            "rooms" => {
+                if data.len() < 6 || data.len() % 6 != 0 {
+                    return Err(format!("Incomplete data: {:?}", &m));
+                }
+                let rows: Vec<&[String]> = data.chunks(6).collect();
+                for r in rows.iter() { gv.completer.saw_room(&r[0]); }
+                /* Column widths, in characters: room, users, owner, status. */
+                let mut w: [usize; 4] = [4, 5, 5, 6];
+                for r in rows.iter() {
+                    for (n, i) in [0usize, 2, 3, 4].iter().enumerate() {
+                        w[n] = w[n].max(r[*i].chars().count());
+                    }
+                }
+                let pad = |x: &str, n: usize| format!("{}{}  ", x,
+                    " ".repeat(n.saturating_sub(x.chars().count())));
+                
+                let mut sl = Line::new();
+                sl.push("* ");
+                sl.pushf(format!("{}{}{}{}topic", pad("room", w[0]), pad("users", w[1]),
+                                  pad("owner", w[2]), pad("status", w[3])),
+                         &scrn.styles().dim_bold);
+                push_here(scrn, gv, sl);
+                for r in rows.iter() {
+                    let mut sl = Line::new();
+                    sl.push("* ");
+                    sl.pushf(pad(&r[0], w[0]), &scrn.styles().high);
+                    sl.push(pad(&r[2], w[1]));
+                    sl.push(pad(&r[3], w[2]));
+                    sl.pushf(pad(&r[4], w[3]), &scrn.styles().dim);
+                    sl.push(&r[5]);
+                    push_here(scrn, gv, sl);
+                }
+            },
+            
+            "announce" => {
+                if data.len() < 2 {
+                    return Err(format!("Incomplete data: {:?}", &m));
+                }
+                let mut sl = Line::new();
+                if data[0].len() == 0 {
+                    sl.pushf("!!! Announcement:", &scrn.styles().announce);
+                } else {
+                    sl.pushf(format!("!!! Announcement from {}:", &data[0]), &scrn.styles().announce);
+                }
+                sl.push(" ");
+                sl.pushf(&data[1], &scrn.styles().high_bold);
+                push_status(scrn, gv, sl, true);
+            },
+            
+            "server_bans" => {
+                if data.len() % 4 != 0 {
+                    return Err(format!("Incomplete data: {:?}", &m));
+                }
+                let mut sl = Line::new();
+                sl.push("* Banned from the server:");
+                if data.len() == 0 { sl.pushf(" nobody", &scrn.styles().dim); }
+                push_here(scrn, gv, sl);
+                for quad in data.chunks(4) {
+                    let age = match quad[3].parse::<u64>() {
+                        Ok(n) => describe_age(n),
+                        Err(_) => String::from("at an unknown time"),
+                    };
+                    let mut sl = Line::new();
+                    sl.push("*   ");
+                    sl.pushf(&quad[0], &scrn.styles().high);
+                    sl.pushf(format!(" ({}, by {}, {})", &quad[1], &quad[2], &age), &scrn.styles().dim);
+                    push_here(scrn, gv, sl);
+                }
+            },
+            
+            "room_info" => {
+                if data.len() < 1 || (data.len() - 1) % 2 != 0 {
+                    return Err(format!("Incomplete data: {:?}", &m));
+                }
+                let mut sl = Line::new();
+                sl.push("* ");
+                sl.pushf(&data[0], &scrn.styles().high);
+                sl.push(":");
+                push_here(scrn, gv, sl);
+                for pair in data[1..].chunks(2) {
+                    let mut sl = Line::new();
+                    sl.push("*   ");
+                    sl.pushf(format!("{}: ", &pair[0]), &scrn.styles().dim);
+                    sl.pushf(&pair[1], &scrn.styles().high);
+                    push_here(scrn, gv, sl);
+                }
+            },
+            
+            "whois" => {
+                if data.len() < 1 || (data.len() - 1) % 2 != 0 {
+                    return Err(format!("Incomplete data: {:?}", &m));
+                }
+                let mut sl = Line::new();
+                sl.push("* ");
+                sl.pushf(&data[0], &scrn.styles().high);
+                sl.push(":");
+                push_here(scrn, gv, sl);
+                for pair in data[1..].chunks(2) {
+                    /* Times come as Unix seconds; show them as ages. */
+                    let (key, val) = match (pair[0].as_str(), pair[1].parse::<u64>()) {
+                        ("connected", Ok(t)) => ("connected", describe_age(t)),
+                        ("last_text", Ok(t)) => ("last spoke", describe_age(t)),
+                        ("last_text", Err(_)) => ("last spoke", String::from("never")),
+                        (k, _) => (k, pair[1].clone()),
+                    };
+                    let mut sl = Line::new();
+                    sl.push("*   ");
+                    sl.pushf(format!("{}: ", key), &scrn.styles().dim);
+                    sl.pushf(&val, &scrn.styles().high);
+                    push_here(scrn, gv, sl);
+                }
             },
             
             "addr" => {
-                match data.get(0) {
+                match data.first() {
                     None => { return Err(format!("Incomplete data: {:?}", &m)); },
                     Some(addr) => {
                         gv.local_addr = addr.clone();
@@ -687,27 +1417,247 @@ fn process_msg(m: Rcvr,
                 let mut sl = Line::new();
                 sl.push("* ");
                 sl.push(alt);
-                scrn.push_line(sl)
+                push_here(scrn, gv, sl)
             },
         },
 
-        msg @ _ => {
+        msg => {
             let msgs = format!("{:?}", msg);
             let s: String = msgs.chars().map(|c| {
                 match c {
                     RETURN => SPACE,
-                    x @ _ => x,
+                    x => x,
                 }
             }).collect();
             let mut sl = Line::new();
             sl.push("# Unsupported Rcvr: ");
             sl.push(&s);
-            scrn.push_line(sl);
+            push_here(scrn, gv, sl);
         },
     }
     return Ok(());
 }
 
+/** Return the index of the window showing `target`, opening one (called
+`name`) if there isn't one.
+*/
+fn window_for(scrn: &mut Screen, gv: &mut Globals, target: Target, name: &str) -> usize {
+    if let Some(n) = gv.windows.iter().position(|t| t == &target) {
+        return n;
+    }
+    gv.windows.push(target);
+    let n = scrn.add_buffer(name);
+    write_window_line(scrn);
+    return n;
+}
+
+/** Return the index of the window showing the current Room. */
+fn room_window(scrn: &mut Screen, gv: &mut Globals) -> usize {
+    let rname = gv.rname.clone();
+    return window_for(scrn, gv, Target::Room(ascollapse(&rname)), &rname);
+}
+
+/** Return whether `text` mentions the user by name (or contains one of
+the configured highlight words).
+*/
+fn mentions_me(gv: &Globals, text: &str) -> bool {
+    if mentions(text, &gv.uname) { return true; }
+    return gv.highlights.iter().any(|w| mentions(text, w));
+}
+
+/** Call the user's attention to a message from `who`, however they've
+configured the client to.
+*/
+fn notify(scrn: &mut Screen, gv: &Globals, who: &str, text: &str) {
+    match gv.notify {
+        Notify::None => {},
+        Notify::Bell => { scrn.alert("\x07"); },
+        Notify::Osc => {
+            /* Control characters would end (or mangle) the escape. */
+            let body: String = format!("{}: {}", who, text).chars()
+                .filter(|c| !c.is_control()).collect();
+            scrn.alert(&format!("\x1b]9;{}\x07", &body));
+        },
+    }
+}
+
+/** Return `l` with a timestamp (if they're turned on) in front of it,
+showing the time `t`. Wrapped rows of the line are indented to start under
+the text after the timestamp.
+*/
+fn stamp(scrn: &Screen, gv: &Globals, l: Line, t: &DateTime<Local>) -> Line {
+    let fmt = match &gv.timestamp {
+        None => { return l; },
+        Some(f) => f,
+    };
+    let mut sl = Line::new();
+    sl.pushf(t.format(fmt).to_string(), &scrn.styles().dim);
+    sl.push(" ");
+    sl.set_indent(sl.len() + l.indent());
+    sl.append(&l);
+    return sl;
+}
+
+/** Add a line to window `n`, stamped with the time `when` (or now),
+updating the window list if it isn't the one being shown. If `log` is set,
+the line is also written to the chat log.
+*/
+fn push_to_at(scrn: &mut Screen, gv: &mut Globals, n: usize, l: Line,
+              mention: bool, log: bool, when: Option<u64>) {
+    let t = match when.and_then(|secs| Local.timestamp_opt(secs as i64, 0).single()) {
+        Some(t) => t,
+        None => Local::now(),
+    };
+    if log { log_line(scrn, gv, n, &l, &t); }
+    let l = stamp(scrn, gv, l, &t);
+    scrn.push_line_to(n, l, mention);
+    if n != scrn.current_buffer() { write_window_line(scrn); }
+}
+
+/** If chat is being logged, write a line shown at time `t` in window `n`
+to the log for its Room or private conversation. If that fails, logging
+stops (with a complaint in the status window).
+*/
+fn log_line(scrn: &mut Screen, gv: &mut Globals, n: usize, l: &Line, t: &DateTime<Local>) {
+    let log = match gv.chatlog.as_mut() {
+        None => { return; },
+        Some(log) => log,
+    };
+    let res = match &gv.windows[n] {
+        Target::Status => { return; },
+        Target::Room(_) => log.write("room", scrn.buffer_name(n), *t, &l.plain()),
+        Target::Query(_) => log.write("priv", scrn.buffer_name(n), *t, &l.plain()),
+    };
+    if let Err(e) = res {
+        error!("{}", &e);
+        gv.chatlog = None;
+        let mut sl = Line::new();
+        sl.pushf(format!("# {} Chat logging has stopped.", &e), &scrn.styles().dim);
+        scrn.push_line_to(0, sl, true);
+        write_window_line(scrn);
+    }
+}
+
+/** Add a line of conversation to window `n` (and the chat log), stamped
+with the current time. */
+fn push_to(scrn: &mut Screen, gv: &mut Globals, n: usize, l: Line, mention: bool) {
+    push_to_at(scrn, gv, n, l, mention, true, None);
+}
+
+/** Add a line to whichever window is being shown. These are replies to
+commands and the like, so they aren't logged. */
+fn push_here(scrn: &mut Screen, gv: &mut Globals, l: Line) {
+    let n = scrn.current_buffer();
+    push_to_at(scrn, gv, n, l, false, false, None);
+}
+
+/** Add a line to the current Room's window. */
+fn push_room(scrn: &mut Screen, gv: &mut Globals, l: Line, mention: bool) {
+    let n = room_window(scrn, gv);
+    push_to(scrn, gv, n, l, mention);
+}
+
+/** Add a line to the window for the private conversation with `who`. */
+fn push_query(scrn: &mut Screen, gv: &mut Globals, who: &str, l: Line, mention: bool) {
+    let n = window_for(scrn, gv, Target::Query(ascollapse(who)), who);
+    /* The server's spelling of the name beats whatever was typed. */
+    if scrn.buffer_name(n) != who {
+        scrn.set_buffer_name(n, who);
+        write_window_line(scrn);
+    }
+    push_to(scrn, gv, n, l, mention);
+}
+
+/** Add a line to the status window. */
+fn push_status(scrn: &mut Screen, gv: &mut Globals, l: Line, mention: bool) {
+    push_to_at(scrn, gv, 0, l, mention, false, None);
+}
+
+/** Respond to a `;window` command: `;window 3` or `;window name` shows
+that window, `;window close` closes the one being shown, and a plain
+`;window` lists them.
+*/
+fn window_command(arg: &str, scrn: &mut Screen, gv: &mut Globals) {
+    if arg.len() == 0 {
+        for n in 0..scrn.buffer_count() {
+            let mut sl = Line::new();
+            sl.push("* ");
+            sl.pushf(format!("{}: ", n + 1), &scrn.styles().dim);
+            sl.pushf(scrn.buffer_name(n), &scrn.styles().high);
+            scrn.push_line(sl);
+        }
+        return;
+    }
+    
+    if arg.to_lowercase() == "close" {
+        let n = scrn.current_buffer();
+        let closable = match &gv.windows[n] {
+            Target::Status => false,
+            Target::Room(r) => r != &ascollapse(&gv.rname),
+            Target::Query(_) => true,
+        };
+        if closable {
+            gv.windows.remove(n);
+            scrn.remove_buffer(n);
+            write_window_line(scrn);
+        } else {
+            let mut sl = Line::new();
+            sl.pushf("# You can't close the status window or the window of the Room you're in.", &scrn.styles().dim);
+            scrn.push_line(sl);
+        }
+        return;
+    }
+    
+    let target = match arg.parse::<usize>() {
+        Ok(n) if n > 0 && n <= scrn.buffer_count() => Some(n - 1),
+        Ok(_) => None,
+        Err(_) => {
+            let collapsed = ascollapse(arg);
+            (0..scrn.buffer_count())
+                .find(|n| ascollapse(scrn.buffer_name(*n)).starts_with(&collapsed))
+        },
+    };
+    match target {
+        Some(n) => switch_window(scrn, n),
+        None => {
+            let mut sl = Line::new();
+            sl.pushf(format!("# There's no window {:?}.", arg), &scrn.styles().dim);
+            scrn.push_line(sl);
+        },
+    }
+}
+
+/** Show window `n`, if there is one. */
+fn switch_window(scrn: &mut Screen, n: usize) {
+    if n < scrn.buffer_count() {
+        scrn.switch_buffer(n);
+        write_window_line(scrn);
+    }
+}
+
+/** Rewrite the list of windows (in the lower-right-hand corner), with
+counts of unread lines (and mentions) in the ones not being shown.
+*/
+fn write_window_line(scrn: &mut Screen) {
+    let mut win_line = Line::new();
+    for n in 0..scrn.buffer_count() {
+        if n > 0 { win_line.push(" "); }
+        let label = format!("{}:{}", n + 1, scrn.buffer_name(n));
+        let (unread, mentions) = scrn.buffer_activity(n);
+        if n == scrn.current_buffer() {
+            win_line.pushf(&label, &scrn.styles().high_bold);
+        } else if mentions > 0 {
+            win_line.pushf(format!("{}({},{}!)", &label, unread, mentions),
+                           &scrn.styles().mention);
+        } else if unread > 0 {
+            win_line.pushf(format!("{}({})", &label, unread), &scrn.styles().status);
+        } else {
+            win_line.pushf(&label, &scrn.styles().dim);
+        }
+    }
+    scrn.set_stat_lr(win_line);
+}
+
 /** When the mode line (in the lower-left-hand corner) should change,
 this updates it.
 */
@@ -716,22 +1666,58 @@ fn write_mode_line(scrn: &mut Screen, gv: &Globals) {
     let mch: &str = match gv.mode {
         Mode::Command => "Com",
         Mode::Input => "Ipt",
+        Mode::Search => "Srch",
     };
-    mode_line.pushf(mch, &scrn.styles().high);
+    mode_line.pushf(mch, &scrn.styles().status);
     mode_line.pushf(" | ", &scrn.styles().dim);
-    mode_line.pushf(&(gv.uname), &scrn.styles().high);
-    mode_line.push(" @ ");
-    mode_line.pushf(&(gv.local_addr), &scrn.styles().high);
+    mode_line.pushf(&(gv.uname), &scrn.styles().status);
+    mode_line.pushf(" @ ", &scrn.styles().status);
+    mode_line.pushf(&(gv.local_addr), &scrn.styles().status);
+    if gv.away {
+        mode_line.pushf(" | ", &scrn.styles().dim);
+        mode_line.pushf("Away", &scrn.styles().high_bold);
+    }
+    if let Some(srch) = &gv.search {
+        mode_line.pushf(" | ", &scrn.styles().dim);
+        if srch.failing {
+            mode_line.pushf("failing ", &scrn.styles().dim);
+        }
+        mode_line.push("reverse-i-search: ");
+        mode_line.pushf(&srch.pattern, &scrn.styles().high_bold);
+    }
+    if let Some(f) = gv.find_prompt.as_ref().or(gv.find.as_ref()) {
+        mode_line.pushf(" | ", &scrn.styles().dim);
+        mode_line.push(if f.backward { "?" } else { "/" });
+        mode_line.pushf(&f.pattern, &scrn.styles().high_bold);
+        if f.failing {
+            mode_line.pushf(" (not found)", &scrn.styles().dim);
+        }
+    }
     scrn.set_stat_ll(mode_line);
 }
 
 fn main() {
     let cfg: ClientConfig = configure();
-    #[cfg(debug)]
-    let the_log_level = simplelog::LevelFilter::Trace;
-    #[cfg(release)]
-    let the_log_level = simplelog::LevelFilter::None;
-    
+    let history = match &cfg.history_file {
+        None => History::new(cfg.history_size),
+        Some(path) => match History::load(path, cfg.history_size) {
+            Ok(h) => h,
+            Err(e) => {
+                println!("{}", e);
+                History::new(cfg.history_size)
+            },
+        },
+    };
+    let chatlog = match &cfg.log_dir {
+        None => None,
+        Some(dir) => match ChatLog::new(dir) {
+            Ok(log) => Some(log),
+            Err(e) => {
+                println!("{}", e);
+                None
+            },
+        },
+    };
     simplelog::WriteLogger::init(simplelog::LevelFilter::Trace,
                                  simplelog::Config::default(),
                                  std::fs::File::create("grel.log").unwrap())
@@ -755,6 +1741,11 @@ fn main() {
     }
     println!("...success. Initializing terminal.");
     
+    let mut completer = Completer::new(cfg.cmd_char, COMMANDS);
+    completer.set_subcommands("op", OP_COMMANDS);
+    completer.set_subcommands("admin", ADMIN_COMMANDS);
+    completer.set_room_commands(ROOM_COMMANDS);
+    
     let mut gv: Globals = Globals {
         uname: cfg.name.clone(),
         rname: String::from("Lobby"),
@@ -765,6 +1756,18 @@ fn main() {
         socket: sck,
         cmd: cfg.cmd_char,
         run: true,
+        away: false,
+        history,
+        search: None,
+        find: None,
+        find_prompt: None,
+        completer,
+        windows: vec![Target::Status],
+        highlights: cfg.highlights.iter().map(|w| ascollapse(w))
+                        .filter(|w| w.len() > 0).collect(),
+        notify: cfg.notify,
+        timestamp: cfg.timestamp.clone(),
+        chatlog,
     };
     
     {
@@ -776,6 +1779,8 @@ fn main() {
                 std::process::exit(1);
             },
         };
+        let sixteen = cfg.colors.as_ref()
+            .and_then(|c| c.sixteen_colors).unwrap_or(false);
         if let Some(cols) = cfg.colors {
             let uline = cols.underline_as_bold.unwrap_or(false);
             scrn.set_styles(
@@ -784,15 +1789,24 @@ fn main() {
                 cols.highlight_foreground,
                 cols.highlight_background,
                 uline);
+            scrn.set_nick_colors(cols.nick_colors.as_deref(), sixteen);
+        }
+        if let Some(theme) = &cfg.theme {
+            /* `sixteen_colors` beats a theme's name colors. */
+            let mut sty = scrn.styles().clone();
+            theme.apply(&mut sty, !sixteen);
+            scrn.use_styles(sty);
         }
         
         let mut addr_line = Line::new();
-        addr_line.pushf(&gv.server_addr, &scrn.styles().high);
+        addr_line.pushf(&gv.server_addr, &scrn.styles().status);
         scrn.set_stat_ul(addr_line);
         let mut room_line = Line::new();
-        room_line.pushf(&gv.rname, &scrn.styles().high);
+        room_line.pushf(&gv.rname, &scrn.styles().status);
         scrn.set_stat_ur(room_line);
         write_mode_line(&mut scrn, &gv);
+        let n = room_window(&mut scrn, &mut gv);
+        switch_window(&mut scrn, n);
         
         /* The 'main_loop repeats until the program should end, generally
         after disconnection.
@@ -810,7 +1824,7 @@ fn main() {
                         if let Err(e) = scrn.refresh(&mut term) {
                             gv.messages.push(format!("Error refreshing screen: {}", e));
                             break 'main_loop;
-                        } else if gv.run == false {
+                        } else if !gv.run {
                             break 'main_loop;
                         }
                     },
@@ -859,7 +1873,7 @@ fn main() {
                                 //let newmsg: Rcvr = unsafe { std::mem::transmute(msg) };
                                 match process_msg(msg, &mut scrn, &mut gv) {
                                     Ok(()) => { 
-                                        if gv.run == false {
+                                        if !gv.run {
                                             break 'main_loop;
                                         }
                                     },
@@ -896,6 +1910,12 @@ fn main() {
         }
     }
     
+    if let Some(path) = &cfg.history_file {
+        if let Err(e) = gv.history.save(path) {
+            gv.messages.push(e);
+        }
+    }
+    
     for m in &gv.messages {
         println!("{}", &m);
     }
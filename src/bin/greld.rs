@@ -6,11 +6,15 @@ The `grel` daemon (server) process.
 updated 2021-02-01
 */
 
+/* Explicit `return`s and `.len() == 0` are house style. */
+#![allow(clippy::needless_return, clippy::len_zero)]
+
 use std::collections::HashMap;
 use std::net::TcpListener;
-use std::sync::mpsc;
+use std::sync::{Arc, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
-use std::time::{Instant, Duration};
+use std::time::{Instant, Duration, SystemTime};
 #[allow(unused_imports)]
 use log::{debug, warn, trace};
 use simplelog::WriteLogger;
@@ -19,9 +23,12 @@ use smallvec::SmallVec;
 //use grel::proto2::*;
 use grel::proto3::*;
 use grel::user::*;
-use grel::room::Room;
+use grel::room::{Room, Role, Perm, Mark};
 use grel::sock::Sock;
-use grel::config::ServerConfig;
+use grel::config::{AdminAccount, ServerConfig, parse_log_level};
+use grel::passwd;
+use grel::matching::Pattern;
+use grel::state::{Account, BanState, Memo, RoomState, Snapshot};
 
 // const DEBUG: bool = true;
 
@@ -31,6 +38,11 @@ const TEXT_SIZE: usize = 2;
 const ROOM_SIZE: usize = 64;
 
 static BLOCK_TIMEOUT: Duration = Duration::from_millis(5000);
+static FLUSH_TICK: Duration = Duration::from_millis(50);
+/* Minimum time between a `User`'s tries at a key or password. */
+static ATTEMPT_INTERVAL: Duration = Duration::from_millis(1000);
+/* Seconds before a scheduled shutdown at which everyone gets warned. */
+const SHUTDOWN_WARNINGS: &[u64] = &[3600, 1800, 900, 600, 300, 120, 60, 30, 10, 5];
 
 /* The Context is instantiated in process_room() and passed to each of
 the functions that handles receiving messages from clients.
@@ -42,6 +54,7 @@ struct Context<'a>{
     ustr: &'a mut HashMap<String, u64>,
     rmap: &'a mut HashMap<u64, Room>,
     rstr: &'a mut HashMap<String, u64>,
+    srv:  &'a mut ServerState,
 }
 
 impl Context<'_> {
@@ -76,10 +89,226 @@ impl Context<'_> {
     }
     
     fn gustr(&self, u_idstr: &str) -> Option<u64> { 
-        if let Some(n) = self.ustr.get(u_idstr) { Some(*n) } else { None }
+        self.ustr.get(u_idstr).copied()
     }
     fn grstr(&self, r_idstr: &str) -> Option<u64> { 
-        if let Some(n) = self.rstr.get(r_idstr) { Some(*n) } else { None }
+        self.rstr.get(r_idstr).copied()
+    }
+}
+
+/* Server-wide state that doesn't belong to any `Room` or `User`: the
+addresses banned from the whole server, registered names, rooms restored
+from saved state that nobody has joined yet, undelivered memos, and where
+to keep the audit trail of administrator actions.
+*/
+struct ServerState {
+    bans: Vec<(String, Mark)>,
+    /* Registered names, keyed by collapsed name. */
+    accounts: HashMap<String, Account>,
+    /* Saved `Room`s not currently open, keyed by collapsed name. */
+    dormant: HashMap<String, RoomState>,
+    /* Undelivered memos, keyed by collapsed recipient name, oldest first. */
+    memos: HashMap<String, Vec<Memo>>,
+    audit_path: String,
+    /* When a scheduled shutdown will happen, and why. */
+    shutdown: Option<(Instant, String)>,
+    /* The last `SHUTDOWN_WARNINGS` threshold warned about. */
+    warned: u64,
+    /* If an administrator has asked for the configuration to be reloaded,
+    that administrator's user ID. */
+    reload: Option<u64>,
+    /* When each address last tried a key or password. */
+    attempts: HashMap<String, Instant>,
+}
+
+impl ServerState {
+    fn new(cfg: &ServerConfig) -> ServerState {
+        ServerState {
+            bans: Vec::new(),
+            accounts: HashMap::new(),
+            dormant: HashMap::new(),
+            memos: HashMap::new(),
+            audit_path: cfg.audit_log.clone(),
+            shutdown: None,
+            warned: u64::MAX,
+            reload: None,
+            attempts: HashMap::new(),
+        }
+    }
+    
+    /* Schedule a shutdown `secs` from now, returning the text of the
+    initial warning. */
+    fn schedule_shutdown(&mut self, secs: u64, why: &str) -> String {
+        self.shutdown = Some((Instant::now() + Duration::from_secs(secs), String::from(why)));
+        self.warned = warning_threshold(secs).unwrap_or(u64::MAX);
+        shutdown_warning(secs, why)
+    }
+    
+    /* If a scheduled shutdown has gotten close enough to warrant a fresh
+    warning, return the text of that warning. */
+    fn countdown(&mut self, now: Instant) -> Option<String> {
+        let (when, why) = self.shutdown.as_ref()?;
+        let left = when.saturating_duration_since(now);
+        let secs = left.as_secs() + if left.subsec_nanos() > 0 { 1 } else { 0 };
+        let t = warning_threshold(secs)?;
+        if t >= self.warned { return None; }
+        self.warned = t;
+        Some(shutdown_warning(secs, why))
+    }
+    
+    /* Take on the durable state saved in `snap`. Saved `Room`s all start
+    out dormant, and are recreated when somebody joins them. */
+    fn restore(&mut self, snap: Snapshot) {
+        for b in snap.bans.iter() {
+            let mut m = Mark::new(0, &b.name, &b.by);
+            m.when = SystemTime::UNIX_EPOCH + Duration::from_secs(b.when);
+            self.bans.push((b.addr.clone(), m));
+        }
+        for a in snap.accounts.into_iter() {
+            self.accounts.insert(ascollapse(&a.name), a);
+        }
+        for st in snap.rooms.into_iter() {
+            self.dormant.insert(ascollapse(&st.name), st);
+        }
+        for m in snap.memos.into_iter() {
+            self.memos.entry(m.to.clone()).or_default().push(m);
+        }
+    }
+    
+    /* Gather up everything worth saving. The lobby is recreated from the
+    configuration each time, so it isn't saved; destroyed rooms are gone. */
+    fn snapshot(&self, rmap: &HashMap<u64, Room>) -> Snapshot {
+        let mut rooms: Vec<RoomState> = rmap.iter()
+            .filter(|(rid, r)| **rid != 0 && !r.is_destroyed())
+            .map(|(_, r)| r.snapshot())
+            .collect();
+        rooms.extend(self.dormant.values().cloned());
+        rooms.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut accounts: Vec<Account> = self.accounts.values().cloned().collect();
+        accounts.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut memos: Vec<Memo> = self.memos.values().flatten().cloned().collect();
+        memos.sort_by_key(|m| m.sent);
+        let bans = self.bans.iter().map(|(addr, m)| BanState {
+            addr: addr.clone(),
+            name: m.name.clone(),
+            by: m.by.clone(),
+            when: unix_secs(m.when),
+        }).collect();
+        
+        Snapshot {
+            version: grel::state::VERSION,
+            saved: unix_secs(SystemTime::now()),
+            bans,
+            accounts,
+            rooms,
+            memos,
+        }
+    }
+    
+    /* Throw away memos older than `expiry`. */
+    fn prune_memos(&mut self, expiry: Duration) {
+        let cutoff = unix_secs(SystemTime::now()).saturating_sub(expiry.as_secs());
+        for v in self.memos.values_mut() { v.retain(|m| m.sent >= cutoff); }
+        self.memos.retain(|_, v| v.len() > 0);
+    }
+    
+    /* Take any memos waiting for the `User`--those for the registered
+    name he's identified as, or if he isn't identified, those for his
+    current name (unless it's registered to someone else)--and return
+    them ready to deliver. */
+    fn take_memos(&mut self, u: &User) -> Envs {
+        let mut envz = Envs::new0();
+        let key = match u.get_account() {
+            Some(a) => a.to_string(),
+            None if self.accounts.contains_key(u.get_idstr()) => { return envz; },
+            None => u.get_idstr().to_string(),
+        };
+        if let Some(memos) = self.memos.remove(&key) {
+            for m in memos.iter() {
+                let sent = m.sent.to_string();
+                let dat: [&str; 3] = [&m.from, &sent, &m.text];
+                let env = Env::new(
+                    End::Server,
+                    End::User(u.get_id()),
+                    &Sndr::Misc {
+                        what: "memo",
+                        data: &dat,
+                        alt: &format!("Memo from {} ({}): {}", &m.from,
+                                      describe_age(m.sent), &m.text),
+                    });
+                envz.as_mut().push(env);
+            }
+        }
+        envz
+    }
+    
+    /* Return whether connections from `addr` (with or without port)
+    should be refused. */
+    fn is_banned(&self, addr: &str) -> bool {
+        let ip = addr_ip(addr);
+        self.bans.iter().any(|(a, _)| *a == ip)
+    }
+    
+    /* Record that `addr` (an IP) is trying a key or password now. If its
+    last try was less than `interval` ago, nothing is recorded and the time
+    remaining until another is allowed is returned instead. */
+    fn try_attempt(&mut self, addr: &str, interval: Duration) -> Result<(), Duration> {
+        let now = Instant::now();
+        self.attempts.retain(|_, t| now.duration_since(*t) < interval);
+        if let Some(t) = self.attempts.get(addr) {
+            return Err(interval - now.duration_since(*t));
+        }
+        self.attempts.insert(addr.to_string(), now);
+        return Ok(());
+    }
+    
+    /* Append a line recording an administrator's action to the audit
+    trail. Failure to do so is logged, but otherwise ignored. */
+    fn audit(&self, account: &str, uname: &str, uid: u64, action: &str) {
+        use std::io::Write;
+        let line = format!("{} [{} as {} (u{})] {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            account, uname, uid, action);
+        let res = std::fs::OpenOptions::new().create(true).append(true)
+            .open(&self.audit_path)
+            .and_then(|mut f| f.write_all(line.as_bytes()));
+        if let Err(e) = res {
+            warn!("Error writing to audit log {:?}: {}", &self.audit_path, &e);
+        }
+    }
+}
+
+/* Return the smallest of the `SHUTDOWN_WARNINGS` that's at least `secs`. */
+fn warning_threshold(secs: u64) -> Option<u64> {
+    SHUTDOWN_WARNINGS.iter().rev().find(|t| secs <= **t).copied()
+}
+
+/* Describe a number of seconds in the largest whole unit that fits. */
+fn describe_secs(secs: u64) -> String {
+    let (n, unit) = if secs >= 3600 && secs.is_multiple_of(3600) {
+        (secs / 3600, "hour")
+    } else if secs >= 60 && secs.is_multiple_of(60) {
+        (secs / 60, "minute")
+    } else {
+        (secs, "second")
+    };
+    format!("{} {}{}", n, unit, if n == 1 { "" } else { "s" })
+}
+
+fn shutdown_warning(secs: u64, why: &str) -> String {
+    let mut txt = format!("The server will shut down in {}.", describe_secs(secs));
+    if why.len() > 0 {
+        txt.push(' ');
+        txt.push_str(why);
+    }
+    txt
+}
+
+/* Strip the port (if any) from a socket address, leaving just the IP. */
+fn addr_ip(addr: &str) -> String {
+    match addr.parse::<std::net::SocketAddr>() {
+        Ok(a) => a.ip().to_string(),
+        Err(_) => String::from(addr),
     }
 }
 
@@ -121,18 +350,33 @@ impl AsMut<SmallVec<[Env; ENVS_SIZE]>> for Envs {
 }
 
 
-fn match_string<T>(s: &str, hash: &HashMap<String, T>) -> Vec<String> {
-    let mut v: Vec<String> = Vec::new();
-    for k in hash.keys() {
-        if k.starts_with(s) { v.push(String::from(k)); }
+/* If a query's results were cut short, return a notice to that effect
+for the `User` with the given ID.
+*/
+fn truncation_notice(uid: u64, shown: usize, total: usize) -> Option<Env> {
+    if shown >= total { return None; }
+    let env = Env::new(
+        End::Server,
+        End::User(uid),
+        &Sndr::Info(&format!("Showing the first {} of {} matches; try a more specific pattern.",
+                             shown, total)));
+    return Some(env);
+}
+
+/* Return the name of the given `Room`'s owner, or an empty string if it
+has none (like the Lobby) or the owner can't be found.
+*/
+fn owner_name<'a>(r: &Room, umap: &'a HashMap<u64, User>) -> &'a str {
+    match umap.get(&r.get_owner()) {
+        Some(u) => u.get_name(),
+        None => "",
     }
-    return v;
 }
 
 fn append_comma_delimited_list<T: AsRef<str>>(base: &mut String, v: &[T]) {
     let mut v_iter = v.iter();
     if let Some(x) = v_iter.next() { base.push_str(x.as_ref()); }
-    while let Some(x) = v_iter.next() {
+    for x in v_iter {
         base.push_str(", ");
         base.push_str(x.as_ref());
     }
@@ -167,14 +411,13 @@ fn listen(addr: String, tx: mpsc::Sender<User>) {
             Err(e) => { debug!("listen(): Error accepting connection: {}", &e); },
             Ok(stream) => {
                 debug!("listen(): Accepted connection from {:?}", stream.peer_addr().unwrap());
-                let new_sock: Sock;
-                match Sock::new(stream) {
+                let new_sock: Sock = match Sock::new(stream) {
                     Err(e) => {
                         debug!("listen(): Error setting up new Sock: {}", &e);
                         continue;
                     },
-                    Ok(x) => { new_sock = x; },
-                }
+                    Ok(x) => x,
+                };
                 let mut u = User::new(new_sock, new_user_id);
                 match initial_negotiation(&mut u) {
                     Err(e) => { debug!("listen(): Error negotiating initial protocol: {}", &e); },
@@ -183,7 +426,7 @@ fn listen(addr: String, tx: mpsc::Sender<User>) {
                         if let Err(e) = tx.send(u) {
                             debug!("listen(): Error sending client through channel: {}", &e);
                         } else {
-                            new_user_id = new_user_id + 1;
+                            new_user_id += 1;
                         }
                     },
                 }
@@ -194,7 +437,7 @@ fn listen(addr: String, tx: mpsc::Sender<User>) {
 
 fn first_free_id<T: Sized>(map: &HashMap<u64, T>) -> u64 {
     let mut n: u64 = 0;
-    while let Some(_) = map.get(&n) { n = n + 1; }
+    while map.get(&n).is_some() { n += 1; }
     return n;
 }
 
@@ -206,9 +449,34 @@ to the various types of `proto2::Msg` pulled out of a given user's `sock`.
 */
 
 /// In response to Msg::Text{ _, lines }
-
 fn do_text(ctxt: &mut Context, lines: Vec<String>)
 -> Result<Envs, String> {
+    {
+        let r = ctxt.grmap(ctxt.rid)?;
+        if r.is_muted(&ctxt.uid) {
+            let env = Env::new(
+                End::Server,
+                End::User(ctxt.uid),
+                &Sndr::Err(&format!("You have been muted in {}.", r.get_name())));
+            return Ok(Envs::new1(env));
+        }
+    }
+    {
+        let uid = ctxt.uid;
+        let r = ctxt.grmap_mut(ctxt.rid)?;
+        if let Err(wait) = r.try_post(uid) {
+            /* Round up, so nobody is told to wait "0 seconds". */
+            let secs = wait.as_secs() + if wait.subsec_nanos() > 0 { 1 } else { 0 };
+            let env = Env::new(
+                End::Server,
+                End::User(uid),
+                &Sndr::Err(&format!("{} is in slow mode; you can speak again in {} s.",
+                                    r.get_name(), secs)));
+            return Ok(Envs::new1(env));
+        }
+    }
+    
+    ctxt.gumap_mut(ctxt.uid)?.mark_text();
     let u = ctxt.gumap(ctxt.uid)?;
     let mut linesref: SmallVec<[&str; TEXT_SIZE]> = SmallVec::new();
     for s in lines.iter() { linesref.push(s.as_str()); }
@@ -226,7 +494,6 @@ fn do_text(ctxt: &mut Context, lines: Vec<String>)
 }
 
 /// In response to Msg::Priv { who, text }
-
 fn do_priv(ctxt: &mut Context, who: String, text: String)
 -> Result<Envs, String> {
     let u = ctxt.gumap(ctxt.uid)?;
@@ -268,12 +535,25 @@ fn do_priv(ctxt: &mut Context, who: String, text: String)
             who: u.get_name(),
             text: &text,
         });
+    let mut envz = Envs::new2(echo_env, to_env);
+    
+    if let Some(away) = tgt_u.get_away() {
+        let dat: [&str; 2] = [tgt_u.get_name(), away];
+        let env = Env::new(
+            End::User(tgt_uid),
+            End::User(ctxt.uid),
+            &Sndr::Misc {
+                what: "away_reply",
+                data: &dat,
+                alt: &format!("{} is away: {}", tgt_u.get_name(), away),
+            });
+        envz.as_mut().push(env);
+    }
     
-    return Ok(Envs::new2(echo_env, to_env));
+    return Ok(envz);
 }
 
 /// In response to Msg::Name(new_candidate)
-
 fn do_name(ctxt: &mut Context, cfg: &ServerConfig, new_candidate: String)
 -> Result<Envs, String> {
     let new_str = ascollapse(&new_candidate);
@@ -303,6 +583,17 @@ fn do_name(ctxt: &mut Context, cfg: &ServerConfig, new_candidate: String)
             return Ok(Envs::new1(env));
         }
     }
+    if let Some(a) = ctxt.srv.accounts.get(&new_str) {
+        let u = ctxt.gumap(ctxt.uid)?;
+        if u.get_account() != Some(new_str.as_str()) {
+            let env = Env::new(
+                End::Server,
+                End::User(ctxt.uid),
+                &Sndr::Err(&format!("The name \"{}\" is registered; you must identify to use it.",
+                                  &a.name)));
+            return Ok(Envs::new1(env));
+        }
+    }
     
     /* The last part of this function is a little wonky. An extra scope
     with some uninitialized upvals are introduced to work around the
@@ -333,12 +624,19 @@ fn do_name(ctxt: &mut Context, cfg: &ServerConfig, new_candidate: String)
     let _ = ctxt.ustr.remove(&old_idstr);
 
     ctxt.ustr.insert(new_idstr, ctxt.uid);
-    return Ok(Envs::new1(env));
+    restore_blocks(ctxt.umap, &ctxt.srv.accounts, ctxt.uid);
+    let mut envz = Envs::new1(env);
+    /* (Identified users get their memos from `do_identify()`.) */
+    if let Some(u) = ctxt.umap.get(&ctxt.uid) {
+        if u.get_account().is_none() {
+            for env in ctxt.srv.take_memos(u).as_mut().drain(..) { envz.as_mut().push(env); }
+        }
+    }
+    return Ok(envz);
 }
 
 /// In response to Msg::Join(room_name)
-
-fn do_join(ctxt: &mut Context, cfg: &ServerConfig, room_name: String)
+fn do_join(ctxt: &mut Context, cfg: &ServerConfig, room_name: String, key: Option<String>)
 -> Result<Envs, String> {
     let collapsed = ascollapse(&room_name);
     if collapsed.len() == 0 {
@@ -355,49 +653,99 @@ fn do_join(ctxt: &mut Context, cfg: &ServerConfig, room_name: String)
                               &cfg.max_room_name_length)));
         return Ok(Envs::new1(env));
     }
+    if key.is_some() {
+        if let Some(v) = throttle_attempt(ctxt)? { return Ok(v); }
+    }
     
     let tgt_rid = match ctxt.grstr(&collapsed) {
         Some(n) => n,
+        None if ctxt.srv.dormant.contains_key(&collapsed) => {
+            /* Saved rooms come back with their old settings, and go back
+            to sleep if this join doesn't succeed. */
+            let st = ctxt.srv.dormant.remove(&collapsed).unwrap();
+            let new_id = first_free_id(ctxt.rmap);
+            ctxt.rstr.insert(collapsed, new_id);
+            ctxt.rmap.insert(new_id, Room::restore(new_id, &st, ctxt.uid));
+            new_id
+        },
         None => {
-            let new_id = first_free_id(&ctxt.rmap);
-            let new_room = Room::new(new_id, room_name.clone(), ctxt.uid);
+            let new_id = first_free_id(ctxt.rmap);
+            let mut new_room = Room::new(new_id, room_name.clone(), ctxt.uid);
+            /* A key supplied when creating a room becomes that room's key. */
+            if let Some(k) = &key {
+                new_room.set_key(Some(k))?;
+            }
             ctxt.rstr.insert(collapsed, new_id);
             ctxt.rmap.insert(new_id, new_room);
             let mu = ctxt.gumap_mut(ctxt.uid)?;
 
-            mu.deliver_msg(&Sndr::Info(&format!("You create room \"{}\".",
-                                                &room_name)));
+            if key.is_some() {
+                mu.deliver_msg(&Sndr::Info(&format!("You create room \"{}\" with the supplied key.",
+                                                    &room_name)));
+            } else {
+                mu.deliver_msg(&Sndr::Info(&format!("You create room \"{}\".",
+                                                    &room_name)));
+            }
             new_id
         },
     };
     
     let uname: String;
+    let uidstr: String;
+    let account: Option<String>;
     let uid = ctxt.uid;
     let rid = ctxt.rid;
     {
         let u = ctxt.gumap(ctxt.uid)?;
         uname = u.get_name().to_string();
+        uidstr = u.get_idstr().to_string();
+        account = u.get_account().map(|a| a.to_string());
     }
     
     {
         let targ_r = ctxt.grmap_mut(tgt_rid)?;
+        /* Anyone returning to claim a saved rank is let in as if invited,
+        and a returning owner or operator even if banned. */
+        let claim = account.as_deref().and_then(|a| targ_r.claim_of(a));
         if tgt_rid == rid {
             let env = Env::new(
                 End::Server,
                 End::User(uid),
                 &Sndr::Info(&format!("You are already in \"{}\".", targ_r.get_name())));
             return Ok(Envs::new1(env));
-        } else if targ_r.is_banned(&uid) {
+        } else if targ_r.is_banned(&uid, &uidstr) && claim.is_none_or(|r| r < Role::Operator) {
             let env = Env::new(
                 End::Server,
                 End::User(uid),
                 &Sndr::Info(&format!("You are banned from \"{}\".", targ_r.get_name())));
             return Ok(Envs::new1(env));
-        } else if targ_r.closed && !targ_r.is_invited(&uid) {
+        }
+        if targ_r.closed && !targ_r.is_invited(&uid, &uidstr) && claim.is_none() {
+            let msg = match (&key, targ_r.has_key()) {
+                (Some(k), true) => if targ_r.check_key(k) {
+                    None
+                } else {
+                    Some(format!("Wrong key for \"{}\".", targ_r.get_name()))
+                },
+                (None, true) => Some(format!(
+                    "\"{}\" is closed, but will admit you with its key.",
+                    targ_r.get_name())),
+                (_, false) => Some(format!("\"{}\" is closed.", targ_r.get_name())),
+            };
+            if let Some(msg) = msg {
+                let env = Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info(&msg));
+                return Ok(Envs::new1(env));
+            }
+        }
+        if targ_r.is_full_for(uid) && claim.is_none_or(|r| r < Role::Moderator) {
             let env = Env::new(
                 End::Server,
                 End::User(uid),
-                &Sndr::Info(&format!("\"{}\" is closed.", targ_r.get_name())));
+                &Sndr::Info(&format!("\"{}\" is full ({} users).", targ_r.get_name(),
+                                     targ_r.get_users().len())));
             return Ok(Envs::new1(env));
         }
         targ_r.join(uid);
@@ -411,6 +759,24 @@ fn do_join(ctxt: &mut Context, cfg: &ServerConfig, room_name: String)
                 alt: &format!("{} joins {}.", &uname, targ_r.get_name()),
             });
         targ_r.enqueue(join_env);
+        if let Some(a) = &account {
+            if let Some(role) = targ_r.claim(uid, a) {
+                let env = Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info(&format!("Your rank of {} in {} has been restored.",
+                                         role.name(), targ_r.get_name())));
+                targ_r.enqueue(env);
+            }
+        }
+        if targ_r.get_topic().len() > 0 {
+            let topic_env = Env::new(
+                End::Server,
+                End::User(uid),
+                &Sndr::Info(&format!("The topic of {} is \"{}\".",
+                                     targ_r.get_name(), targ_r.get_topic())));
+            targ_r.enqueue(topic_env);
+        }
     }
     
     let cur_r = ctxt.grmap_mut(ctxt.rid)?;
@@ -429,7 +795,6 @@ fn do_join(ctxt: &mut Context, cfg: &ServerConfig, room_name: String)
 }
 
 /// In response to Msg::Block(user_name)
-
 fn do_block(ctxt: &mut Context, user_name: String)
 -> Result<Envs, String> {
     let collapsed = ascollapse(&user_name);
@@ -471,11 +836,16 @@ fn do_block(ctxt: &mut Context, user_name: String)
         mu.deliver_msg(&Sndr::Err(&format!("You are already blocking {}.", &blocked_name)));
     };
     
+    /* Registered names remember their blocks across restarts. */
+    let account = mu.get_account().map(|a| a.to_string());
+    if let Some(a) = account.and_then(|a| ctxt.srv.accounts.get_mut(&a)) {
+        if !a.blocks.contains(&collapsed) { a.blocks.push(collapsed); }
+    }
+    
     return Ok(Envs::new0());
 }
 
 /// In response to Msg::Unblock(user_name)
-
 fn do_unblock(ctxt: &mut Context, user_name: String)
 -> Result<Envs, String> {
     let collapsed = ascollapse(&user_name);
@@ -517,11 +887,229 @@ fn do_unblock(ctxt: &mut Context, user_name: String)
         mu.deliver_msg(&Sndr::Err(&format!("You were not blocking {}.", &blocked_name)));
     }
     
+    let account = mu.get_account().map(|a| a.to_string());
+    if let Some(a) = account.and_then(|a| ctxt.srv.accounts.get_mut(&a)) {
+        a.blocks.retain(|b| *b != collapsed);
+    }
+    
     return Ok(Envs::new0());
 }
 
-/// In response to Msg::Logout(salutation)
+/* Re-establish the saved blocks involving the `User` with the given ID:
+those his registered name (if he's identified) has on others present,
+and those others' registered names have on him.
+*/
+fn restore_blocks(umap: &mut HashMap<u64, User>, accounts: &HashMap<String, Account>, uid: u64) {
+    let (idstr, own) = match umap.get(&uid) {
+        None => { return; },
+        Some(u) => (
+            u.get_idstr().to_string(),
+            u.get_account().and_then(|a| accounts.get(a)),
+        ),
+    };
+    
+    let mut mine: Vec<u64> = Vec::new();
+    let mut theirs: Vec<u64> = Vec::new();
+    for (ouid, ou) in umap.iter() {
+        if *ouid == uid { continue; }
+        if let Some(a) = own {
+            if a.blocks.iter().any(|b| b == ou.get_idstr()) { mine.push(*ouid); }
+        }
+        if let Some(a) = ou.get_account().and_then(|a| accounts.get(a)) {
+            if a.blocks.contains(&idstr) { theirs.push(*ouid); }
+        }
+    }
+    
+    if let Some(u) = umap.get_mut(&uid) {
+        for n in mine.into_iter() { u.block_id(n); }
+    }
+    for n in theirs.into_iter() {
+        if let Some(ou) = umap.get_mut(&n) { ou.block_id(uid); }
+    }
+}
+
+/// In response to Msg::Register(password)
+fn do_register(ctxt: &mut Context, password: String)
+-> Result<Envs, String> {
+    let (name, idstr, current, blocks) = {
+        let u = ctxt.gumap(ctxt.uid)?;
+        let blocks: Vec<String> = u.get_blocks().iter()
+            .filter_map(|n| ctxt.umap.get(n))
+            .map(|ou| ou.get_idstr().to_string())
+            .collect();
+        (u.get_name().to_string(), u.get_idstr().to_string(),
+         u.get_account().map(|a| a.to_string()), blocks)
+    };
+    
+    let err = if password.len() == 0 {
+        Some(String::from("You must supply a password to register your name."))
+    } else if let Some(a) = current.and_then(|a| ctxt.srv.accounts.get(&a)) {
+        Some(format!("You have already identified as \"{}\".", &a.name))
+    } else {
+        ctxt.srv.accounts.get(&idstr)
+            .map(|a| format!("The name \"{}\" is already registered.", &a.name))
+    };
+    if let Some(err) = err {
+        let env = Env::new(End::Server, End::User(ctxt.uid), &Sndr::Err(&err));
+        return Ok(Envs::new1(env));
+    }
+    if let Some(v) = throttle_attempt(ctxt)? { return Ok(v); }
+    
+    let account = Account {
+        name: name.clone(),
+        password: passwd::hash(&password)?,
+        registered: unix_secs(SystemTime::now()),
+        blocks,
+    };
+    ctxt.srv.accounts.insert(idstr.clone(), account);
+    let uid = ctxt.uid;
+    ctxt.gumap_mut(uid)?.set_account(&idstr);
+    let _ = ctxt.grmap_mut(ctxt.rid)?.claim(uid, &idstr);
+    debug!("User {} registered name {:?}", uid, &name);
+    
+    let env = Env::new(
+        End::Server,
+        End::User(uid),
+        &Sndr::Info(&format!("You have registered the name \"{}\".", &name)));
+    return Ok(Envs::new1(env));
+}
+
+/// In response to Msg::Identify { name, password }
+fn do_identify(ctxt: &mut Context, cfg: &ServerConfig, name: String, password: String)
+-> Result<Envs, String> {
+    let uid = ctxt.uid;
+    if let Some(v) = throttle_attempt(ctxt)? { return Ok(v); }
+    let collapsed = ascollapse(&name);
+    let reg_name = match ctxt.srv.accounts.get(&collapsed) {
+        Some(a) if passwd::verify(&a.password, &password) => a.name.clone(),
+        _ => {
+            let env = Env::new(
+                End::Server,
+                End::User(uid),
+                &Sndr::Err("Identification failed."));
+            return Ok(Envs::new1(env));
+        },
+    };
+    if let Some(n) = ctxt.gustr(&collapsed) {
+        if n != uid {
+            let env = Env::new(
+                End::Server,
+                End::User(uid),
+                &Sndr::Err(&format!("Someone else is using the name \"{}\" right now.", &reg_name)));
+            return Ok(Envs::new1(env));
+        }
+    }
+    
+    let mut envz = Envs::new0();
+    let same_name = {
+        let mu = ctxt.gumap_mut(uid)?;
+        mu.set_account(&collapsed);
+        mu.get_idstr() == collapsed
+    };
+    if !same_name {
+        envz = do_name(ctxt, cfg, reg_name.clone())?;
+    }
+    restore_blocks(ctxt.umap, &ctxt.srv.accounts, uid);
+    
+    let r = ctxt.grmap_mut(ctxt.rid)?;
+    let mut txt = format!("You are now identified as \"{}\".", &reg_name);
+    if let Some(role) = r.claim(uid, &collapsed) {
+        txt.push_str(&format!(" Your rank of {} in {} has been restored.",
+                              role.name(), r.get_name()));
+    }
+    let env = Env::new(End::Server, End::User(uid), &Sndr::Info(&txt));
+    envz.as_mut().push(env);
+    
+    if let Some(u) = ctxt.umap.get(&uid) {
+        for env in ctxt.srv.take_memos(u).as_mut().drain(..) { envz.as_mut().push(env); }
+    }
+    return Ok(envz);
+}
+
+/// In response to Msg::Away(msg)
+fn do_away(ctxt: &mut Context, msg: String)
+-> Result<Envs, String> {
+    let msg = msg.trim();
+    let (uid, rid) = (ctxt.uid, ctxt.rid);
+    let mu = ctxt.gumap_mut(uid)?;
+    if msg.len() == 0 && mu.get_away().is_none() {
+        let env = Env::new(
+            End::Server,
+            End::User(uid),
+            &Sndr::Err("You weren't away."));
+        return Ok(Envs::new1(env));
+    }
+    
+    let alt = if msg.len() == 0 {
+        mu.set_away(None);
+        format!("{} is back.", mu.get_name())
+    } else {
+        mu.set_away(Some(msg));
+        format!("{} is away: {}", mu.get_name(), msg)
+    };
+    let dat: [&str; 2] = [mu.get_name(), msg];
+    let env = Env::new(
+        End::Server,
+        End::Room(rid),
+        &Sndr::Misc {
+            what: "away",
+            data: &dat,
+            alt: &alt,
+        });
+    return Ok(Envs::new1(env));
+}
+
+/// In response to Msg::Memo { who, text }
+fn do_memo(ctxt: &mut Context, cfg: &ServerConfig, who: String, text: String)
+-> Result<Envs, String> {
+    let uid = ctxt.uid;
+    let to = ascollapse(&who);
+    let (from, from_idstr) = {
+        let u = ctxt.gumap(uid)?;
+        (u.get_name().to_string(), u.get_idstr().to_string())
+    };
+    
+    let err = if to.len() == 0 {
+        Some(String::from("The recipient name must have at least one non-whitespace character."))
+    } else if text.trim().len() == 0 {
+        Some(String::from("You can't leave an empty memo."))
+    } else if let Some(n) = ctxt.gustr(&to) {
+        let name = ctxt.gumap(n)?.get_name();
+        Some(format!("{} is logged on right now; send a private message instead.", name))
+    } else if ctxt.srv.memos.get(&to).map_or(0, |v| v.len()) >= cfg.memo_quota {
+        Some(format!("There are already too many memos waiting for \"{}\".", &who))
+    } else {
+        None
+    };
+    if let Some(err) = err {
+        let env = Env::new(End::Server, End::User(uid), &Sndr::Err(&err));
+        return Ok(Envs::new1(env));
+    }
+    
+    /* Memos from someone the recipient blocks vanish, like any other
+    message would. */
+    let blocked = match ctxt.srv.accounts.get(&to) {
+        Some(a) => a.blocks.contains(&from_idstr),
+        None => false,
+    };
+    if !blocked {
+        ctxt.srv.memos.entry(to.clone()).or_default().push(Memo {
+            from,
+            to,
+            text,
+            sent: unix_secs(SystemTime::now()),
+        });
+    }
+    
+    let env = Env::new(
+        End::Server,
+        End::User(uid),
+        &Sndr::Info(&format!("Your memo for \"{}\" will be delivered when they next log on.",
+                             who.trim())));
+    return Ok(Envs::new1(env));
+}
 
+/// In response to Msg::Logout(salutation)
 fn do_logout(ctxt: &mut Context, salutation: String)
 -> Result<Envs, String> {
     let mr = match ctxt.rmap.get_mut(&ctxt.rid) {
@@ -552,8 +1140,7 @@ fn do_logout(ctxt: &mut Context, salutation: String)
 }
 
 /// In response to Msg::Query { what, arg }
-
-fn do_query(ctxt: &mut Context, what: String, arg: String)
+fn do_query(ctxt: &mut Context, cfg: &ServerConfig, what: String, arg: String)
 -> Result<Envs, String> {
     match what.as_str() {
         "addr" => {
@@ -574,117 +1161,418 @@ fn do_query(ctxt: &mut Context, what: String, arg: String)
         
         "roster" => {
             let r = ctxt.grmap(ctxt.rid)?; 
-            let op_id = r.get_op();
-            let mut names_list: SmallVec<[&str; ROOM_SIZE]> = SmallVec::with_capacity(r.get_users().len());
+            let mut ranked: SmallVec<[(Role, &str, &str); ROOM_SIZE]> = SmallVec::with_capacity(r.get_users().len());
             
-            for uid in r.get_users().iter().rev() {
-                if *uid != op_id {
-                    match ctxt.umap.get(uid) {
-                        None => { warn!("do_query(r {}, u{} {:?}): no User {}",
-                                       ctxt.rid, ctxt.uid, &what, uid);
-                        },
-                        Some(u) => { names_list.push(u.get_name()); },
-                    }
+            for uid in r.get_users().iter() {
+                match ctxt.umap.get(uid) {
+                    None => { warn!("do_query(r {}, u{} {:?}): no User {}",
+                                   ctxt.rid, ctxt.uid, &what, uid);
+                    },
+                    Some(u) => {
+                        ranked.push((r.role_of(*uid), u.get_name(), u.get_away().unwrap_or("")));
+                    },
                 }
             }
+            /* This sort is stable, so within each rank, users remain in
+            the order in which they joined. */
+            ranked.sort_by_key(|r| std::cmp::Reverse(r.0));
             
-            let mut altstr: String;
-            /* The lobby will never have an operator. It's operator uid is
-            set to 0 (the lowest possible uid is 100). */
-            if op_id == 0 {
-                altstr = format!("{} roster: ", r.get_name());
-                append_comma_delimited_list(&mut altstr, &names_list);
-            } else {
-                let op_name = match ctxt.umap.get(&op_id) {
-                    None => "[ ??? ]",
-                    Some(u) => u.get_name(),
-                };
-                altstr = format!("{} roster: {} (operator) ", r.get_name(), op_name);
-                append_comma_delimited_list(&mut altstr, &names_list);
-                names_list.push(op_name);
+            let mut altstr = format!("{} roster: ", r.get_name());
+            let mut marked: SmallVec<[String; ROOM_SIZE]> = SmallVec::with_capacity(ranked.len());
+            let mut dat: SmallVec<[&str; ROOM_SIZE]> = SmallVec::with_capacity(3 * ranked.len());
+            for (role, name, away) in ranked.iter() {
+                if away.len() > 0 {
+                    marked.push(format!("{}{} (away)", role.marker(), name));
+                } else {
+                    marked.push(format!("{}{}", role.marker(), name));
+                }
+                dat.push(role.marker());
+                dat.push(name);
+                dat.push(away);
             }
-
-            let mut names_ref: SmallVec<[&str; ROOM_SIZE]> = SmallVec::with_capacity(names_list.len());
-            for x in names_list.iter().rev() { names_ref.push(x); }
+            append_comma_delimited_list(&mut altstr, &marked);
             
             let env = Env::new(
                 End::Server,
                 End::User(ctxt.uid),
-                &Sndr::Misc { what: "roster", data: &names_ref, alt: &altstr, }
+                &Sndr::Misc { what: "roster", data: &dat, alt: &altstr, }
             );
             return Ok(Envs::new1(env));
         },
         
         "who" => {
-            let collapsed = ascollapse(&arg);
-            let matches = match_string(&collapsed, ctxt.ustr);
-            let env: Env;
+            let pat = match Pattern::parse(&arg) {
+                Ok(p) => p,
+                Err(e) => {
+                    let env = Env::new(End::Server, End::User(ctxt.uid), &Sndr::Err(&e));
+                    return Ok(Envs::new1(env));
+                },
+            };
+            let (matches, total) = pat.find(ctxt.ustr.keys(), cfg.max_matches);
+            let mut envs = Envs::new0();
             if matches.len() == 0 {
-                env = Env::new(
+                envs.as_mut().push(Env::new(
                     End::Server,
                     End::User(ctxt.uid),
-                    &Sndr::Info(&format!("No users matching the pattern \"{}\".", &collapsed)));
+                    &Sndr::Info(&format!("No users matching the pattern \"{}\".", &pat.text))));
             } else {
                 let mut altstr = String::from("Matching names: ");
-                append_comma_delimited_list(&mut altstr, &matches);
-                let listref: Vec<&str> = matches.iter().map(|x| x.as_str()).collect();
-                env = Env::new(
+                let mut listref: Vec<&str> = Vec::with_capacity(2 * matches.len());
+                let mut described: Vec<String> = Vec::with_capacity(matches.len());
+                for m in matches.iter() {
+                    let away = ctxt.gustr(m)
+                        .and_then(|n| ctxt.umap.get(&n))
+                        .and_then(|u| u.get_away())
+                        .unwrap_or("");
+                    if away.len() > 0 {
+                        described.push(format!("{} (away: {})", m, away));
+                    } else {
+                        described.push(m.clone());
+                    }
+                    listref.push(m);
+                    listref.push(away);
+                }
+                append_comma_delimited_list(&mut altstr, &described);
+                envs.as_mut().push(Env::new(
                     End::Server,
                     End::User(ctxt.uid),
                     &Sndr::Misc { what: "who", data: &listref, alt: &altstr,}
-                );
+                ));
+                if let Some(env) = truncation_notice(ctxt.uid, matches.len(), total) {
+                    envs.as_mut().push(env);
+                }
             }
-            return Ok(Envs::new1(env));
+            return Ok(envs);
         },
         
         "rooms" => {
-            let collapsed = ascollapse(&arg);
-            let matches = match_string(&collapsed, ctxt.rstr);
-            let env: Env;
+            let pat = match Pattern::parse(&arg) {
+                Ok(p) => p,
+                Err(e) => {
+                    let env = Env::new(End::Server, End::User(ctxt.uid), &Sndr::Err(&e));
+                    return Ok(Envs::new1(env));
+                },
+            };
+            let (matches, total) = pat.find(ctxt.rstr.keys(), cfg.max_matches);
             if matches.len() == 0 {
-                env = Env::new(
-                    End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info(&format!("No Rooms matching the pattern \"{}\".", &collapsed)));
-            } else {
-                let mut altstr = String::from("Matching Rooms: ");
-                append_comma_delimited_list(&mut altstr, &matches);
-                let listref: Vec<&str> = matches.iter().map(|x| x.as_str()).collect();
-                env = Env::new(
+                let env = Env::new(
                     End::Server,
                     End::User(ctxt.uid),
-                    &Sndr::Misc { what: "rooms", data: &listref, alt: &altstr, }
-                );
+                    &Sndr::Info(&format!("No Rooms matching the pattern \"{}\".", &pat.text)));
+                return Ok(Envs::new1(env));
             }
-            return Ok(Envs::new1(env));
-        },
-        
-        ukn @ _ => {
+            
+            /* Owned copies of each entry's fields, so `data` can refer to them. */
+            let mut entries: Vec<[String; 6]> = Vec::with_capacity(matches.len());
+            for idstr in matches.iter() {
+                let r = match ctxt.grstr(idstr) {
+                    Some(rid) => ctxt.grmap(rid)?,
+                    None => { continue; },
+                };
+                entries.push([
+                    r.get_name().to_string(),
+                    r.get_idstr().to_string(),
+                    r.get_users().len().to_string(),
+                    owner_name(r, ctxt.umap).to_string(),
+                    String::from(if r.closed { "closed" } else { "open" }),
+                    r.get_topic().to_string(),
+                ]);
+            }
+            
+            let mut altstr = String::from("Matching Rooms: ");
+            let listed: Vec<String> = entries.iter()
+                .map(|e| format!("{} ({})", &e[0], &e[2])).collect();
+            append_comma_delimited_list(&mut altstr, &listed);
+            let dat: Vec<&str> = entries.iter()
+                .flat_map(|e| e.iter().map(|x| x.as_str())).collect();
             let env = Env::new(
                 End::Server,
                 End::User(ctxt.uid),
-                &Sndr::Err(&format!("Unknown \"Query\" type: \"{}\".", ukn)));
-            return Ok(Envs::new1(env));
+                &Sndr::Misc { what: "rooms", data: &dat, alt: &altstr, }
+            );
+            let mut envs = Envs::new1(env);
+            if let Some(env) = truncation_notice(ctxt.uid, matches.len(), total) {
+                envs.as_mut().push(env);
+            }
+            return Ok(envs);
         },
-    }
-}
+        
+        "room_info" => {
+            let collapsed = ascollapse(&arg);
+            let rid = if collapsed.len() == 0 {
+                ctxt.rid
+            } else {
+                match ctxt.grstr(&collapsed) {
+                    Some(n) => n,
+                    None => {
+                        let env = Env::new(
+                            End::Server,
+                            End::User(ctxt.uid),
+                            &Sndr::Info(&format!("There is no Room \"{}\".", &collapsed)));
+                        return Ok(Envs::new1(env));
+                    },
+                }
+            };
+            let r = ctxt.grmap(rid)?;
+            
+            let yes_no = |b: bool| String::from(if b { "yes" } else { "no" });
+            let or_none = |s: String| if s.len() == 0 { String::from("none") } else { s };
+            let pairs: [(&str, String); 8] = [
+                ("id",     r.get_idstr().to_string()),
+                ("users",  r.get_users().len().to_string()),
+                ("owner",  or_none(owner_name(r, ctxt.umap).to_string())),
+                ("closed", yes_no(r.closed)),
+                ("key",    yes_no(r.has_key())),
+                ("topic",  or_none(r.get_topic().to_string())),
+                ("limit",  or_none(r.get_limit().map(|n| n.to_string()).unwrap_or_default())),
+                ("slow",   or_none(r.get_slow().map(|d| d.as_secs().to_string()).unwrap_or_default())),
+            ];
+            
+            let mut dat: Vec<&str> = Vec::with_capacity(1 + 2 * pairs.len());
+            dat.push(r.get_name());
+            let mut altstr = format!("{}: ", r.get_name());
+            let mut listed: Vec<String> = Vec::with_capacity(pairs.len());
+            for (k, v) in pairs.iter() {
+                dat.push(k);
+                dat.push(v);
+                listed.push(format!("{} {}", k, v));
+            }
+            append_comma_delimited_list(&mut altstr, &listed);
+            let env = Env::new(
+                End::Server,
+                End::User(ctxt.uid),
+                &Sndr::Misc { what: "room_info", data: &dat, alt: &altstr, }
+            );
+            return Ok(Envs::new1(env));
+        },
+        
+        "whois" => {
+            let collapsed = ascollapse(&arg);
+            let ouid = if collapsed.len() == 0 {
+                ctxt.uid
+            } else {
+                match ctxt.gustr(&collapsed) {
+                    Some(n) => n,
+                    None => {
+                        let env = Env::new(
+                            End::Server,
+                            End::User(ctxt.uid),
+                            &Sndr::Info(&format!("There is no user whose name matches \"{}\".", &collapsed)));
+                        return Ok(Envs::new1(env));
+                    },
+                }
+            };
+            let is_admin = ctxt.gumap(ctxt.uid)?.get_admin().is_some();
+            /* Only administrators get to see where people connect from. */
+            let addr = if is_admin {
+                Some(ctxt.gumap_mut(ouid)?.get_addr().unwrap_or_else(|| String::from("???")))
+            } else {
+                None
+            };
+            let ou = ctxt.gumap(ouid)?;
+            let room = match room_of(ctxt.rmap, ouid).and_then(|n| ctxt.rmap.get(&n)) {
+                Some(r) => r.get_name().to_string(),
+                None => String::from("none"),
+            };
+            let connected = unix_secs(ou.get_connected());
+            let last_text = ou.get_last_text().map(unix_secs);
+            
+            let mut pairs: Vec<(&str, String)> = vec![
+                ("room",      room),
+                ("connected", connected.to_string()),
+                ("last_text", last_text.map(|t| t.to_string()).unwrap_or_else(|| String::from("none"))),
+                ("away",      ou.get_away().unwrap_or("none").to_string()),
+                ("account",   ou.get_account().unwrap_or("none").to_string()),
+            ];
+            if let Some(a) = addr { pairs.push(("address", a)); }
+            
+            let mut altstr = format!("{}: ", ou.get_name());
+            let mut listed: Vec<String> = Vec::with_capacity(pairs.len());
+            for (k, v) in pairs.iter() {
+                let desc = match *k {
+                    "connected" => describe_age(connected),
+                    "last_text" => last_text.map(describe_age).unwrap_or_else(|| String::from("never")),
+                    _ => v.clone(),
+                };
+                listed.push(format!("{} {}", k, desc));
+            }
+            append_comma_delimited_list(&mut altstr, &listed);
+            
+            let mut dat: Vec<&str> = Vec::with_capacity(1 + 2 * pairs.len());
+            dat.push(ou.get_name());
+            for (k, v) in pairs.iter() {
+                dat.push(k);
+                dat.push(v);
+            }
+            let env = Env::new(
+                End::Server,
+                End::User(ctxt.uid),
+                &Sndr::Misc { what: "whois", data: &dat, alt: &altstr, }
+            );
+            return Ok(Envs::new1(env));
+        },
+        
+        ukn => {
+            let env = Env::new(
+                End::Server,
+                End::User(ctxt.uid),
+                &Sndr::Err(&format!("Unknown \"Query\" type: \"{}\".", ukn)));
+            return Ok(Envs::new1(env));
+        },
+    }
+}
 
-/// In response to Msg::Op(op)
+/* Return the `Perm` a `User` must hold in order to issue the given operator
+subcommand.
+*/
+fn op_perm(op: &RcvOp) -> Perm {
+    match op {
+        RcvOp::Open | RcvOp::Close | RcvOp::Key(_) | RcvOp::Unkey
+            | RcvOp::Topic(_) | RcvOp::Limit(_) | RcvOp::Slow(_) => Perm::Close,
+        RcvOp::Kick(_)              => Perm::Kick,
+        RcvOp::Ban(_) | RcvOp::Unban(_) | RcvOp::Bans => Perm::Ban,
+        RcvOp::Mute(_) | RcvOp::Unmute(_) => Perm::Mute,
+        RcvOp::Invite(_) | RcvOp::Uninvite(_) | RcvOp::Invites => Perm::Invite,
+        RcvOp::Operator(_) | RcvOp::Moderator(_) | RcvOp::Demote(_) => Perm::Promote,
+        RcvOp::Give(_)              => Perm::Give,
+    }
+}
+
+/* Look up the user ID of the `User` named as the target of an operator
+subcommand. If there is no such `User`, the `Err` holds the message that
+should be sent back to the acting `User`.
+*/
+fn op_target(ctxt: &Context, uname: &str) -> Result<u64, Env> {
+    let collapsed = ascollapse(uname);
+    if collapsed.len() == 0 {
+        let env = Env::new(
+            End::Server,
+            End::User(ctxt.uid),
+            &Sndr::Info("That cannot be anyone's user name."));
+        return Err(env);
+    }
+    
+    match ctxt.gustr(&collapsed) {
+        Some(n) => Ok(n),
+        None => {
+            let env = Env::new(
+                End::Server,
+                End::User(ctxt.uid),
+                &Sndr::Info(&format!("No users matching the pattern \"{}\".", &collapsed)));
+            Err(env)
+        },
+    }
+}
+
+/* If the acting `User`'s address has tried a key or password too recently,
+return the message that should be sent back instead of trying another. Each
+try costs an argon2 hash on the main thread, so a flood of them would stall
+the server. (Keyed by address, like server bans, so reconnecting doesn't
+start the clock over.)
+*/
+fn throttle_attempt(ctxt: &mut Context) -> Result<Option<Envs>, String> {
+    let uid = ctxt.uid;
+    let addr = match ctxt.gumap_mut(uid)?.get_addr() {
+        Some(a) => addr_ip(&a),
+        None => format!("u{}", uid),
+    };
+    let wait = match ctxt.srv.try_attempt(&addr, ATTEMPT_INTERVAL) {
+        Ok(()) => { return Ok(None); },
+        Err(d) => d,
+    };
+    let env = Env::new(
+        End::Server,
+        End::User(ctxt.uid),
+        &Sndr::Err(&format!("Too many attempts; you can try again in {} ms.",
+                            wait.as_millis())));
+    return Ok(Some(Envs::new1(env)));
+}
+
+/* If the acting `User` does not strictly outrank the target `User` in the
+current `Room`, return the message that should be sent back to the acting
+`User`.
+*/
+fn op_outrank(ctxt: &Context, ouid: u64) -> Result<Option<Envs>, String> {
+    let r = ctxt.grmap(ctxt.rid)?;
+    let their_role = r.role_of(ouid);
+    if their_role < r.role_of(ctxt.uid) {
+        return Ok(None);
+    }
+    
+    let ou = ctxt.gumap(ouid)?;
+    let env = Env::new(
+        End::Server,
+        End::User(ctxt.uid),
+        &Sndr::Err(&format!("{} is the {} of {}; you cannot do that.",
+                            ou.get_name(), their_role.name(), r.get_name())));
+    return Ok(Some(Envs::new1(env)));
+}
+
+/* Remove the `User` with the given ID from the current `Room` and put him
+in the lobby, messaging everyone involved.
+*/
+fn op_eject(ctxt: &mut Context, ouid: u64) -> Result<Envs, String> {
+    let cur_room_name: String;
+    {
+        let cur_r = ctxt.grmap_mut(ctxt.rid)?;
+        cur_r.leave(ouid);
+        cur_room_name = cur_r.get_name().to_string();
+    }
+    
+    let ku_name: String;
+    {
+        let ku = ctxt.gumap_mut(ouid)?;
+        ku_name = ku.get_name().to_string();
+        let altstr = format!("You have been kicked from {}.", &cur_room_name);
+        let dat: [&str; 1] = [&cur_room_name];
+        ku.deliver_msg(&Sndr::Misc { what: "kick_you", alt: &altstr, data: &dat, });
+    }
+    
+    // If the lobby doesn't exist, the server can go ahead and crash.
+    let lobby = ctxt.rmap.get_mut(&0).unwrap();
+    lobby.join(ouid);
+    let dat: [&str; 2] = [&ku_name, lobby.get_name()];
+    let to_lobby = Env::new(
+        End::Server,
+        End::Room(0),
+        &Sndr::Misc {
+            what: "join",
+            data: &dat,
+            alt: &format!("{} joins {}.", &ku_name, lobby.get_name()),
+        });
+    lobby.enqueue(to_lobby);
+    
+    let dat: [&str; 2] = [&ku_name, &cur_room_name];
+    let env = Env::new(
+        End::Server,
+        End::Room(ctxt.rid),
+        &Sndr::Misc {
+            what: "kick_other",
+            data: &dat,
+            alt: &format!("{} has been kicked from {}.", &ku_name, &cur_room_name),
+        });
+    
+    return Ok(Envs::new1(env));
+}
 
+/// In response to Msg::Op(op)
 fn do_op(ctxt: &mut Context, op: RcvOp)
 -> Result<Envs, String> {
-    {
-        let r = ctxt.grmap(ctxt.rid)?;
-        if r.get_op() != ctxt.uid {
+    let (uid, rid) = (ctxt.uid, ctxt.rid);
+    let my_role = {
+        let r = ctxt.grmap(rid)?;
+        let my_role = r.role_of(uid);
+        if !my_role.can(op_perm(&op)) {
             let env = Env::new(
                 End::Server,
-                End::User(ctxt.uid),
-                &Sndr::Err("You are not the operator of this Room."));
+                End::User(uid),
+                &Sndr::Err(&format!("Your rank in {} ({}) does not permit that.",
+                                    r.get_name(), my_role.name())));
             return Ok(Envs::new1(env));
         }
-    }
+        my_role
+    };
     
-    let (uid, rid) = (ctxt.uid, ctxt.rid);
     let op_name = {
         let u = ctxt.gumap(ctxt.uid)?;
         u.get_name().to_string()
@@ -727,32 +1615,102 @@ fn do_op(ctxt: &mut Context, op: RcvOp)
             }
         },
         
-        RcvOp::Give(ref new_name) => {
-            let collapsed = ascollapse(&new_name);
-            if collapsed.len() == 0 {
+        RcvOp::Key(ref key) => {
+            if key.len() == 0 {
                 let env = Env::new(
                     End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Err("That cannot be anyone's user name."));
+                    End::User(uid),
+                    &Sndr::Err("A room key cannot be empty."));
                 return Ok(Envs::new1(env));
             }
-            
-            let ouid = match ctxt.ustr.get(&collapsed) {
-                None => {
-                    let env = Env::new(
-                        End::Server,
-                        End::User(ctxt.uid),
-                        &Sndr::Info(&format!("No users matching the pattern \"{}\".", &collapsed)));
-                    return Ok(Envs::new1(env));
-                },
-                Some(n) => *n,
+            let cur_r = ctxt.grmap_mut(rid)?;
+            let changed = cur_r.has_key();
+            cur_r.set_key(Some(key))?;
+            let env = Env::new(
+                End::Server,
+                End::Room(rid),
+                &Sndr::Info(&format!("{} has {} the key for {}.", &op_name,
+                    if changed { "changed" } else { "set" }, cur_r.get_name())));
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Unkey => {
+            let cur_r = ctxt.grmap_mut(rid)?;
+            if !cur_r.has_key() {
+                let env = Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info(&format!("{} has no key.", cur_r.get_name())));
+                return Ok(Envs::new1(env));
+            }
+            cur_r.set_key(None)?;
+            let env = Env::new(
+                End::Server,
+                End::Room(rid),
+                &Sndr::Info(&format!("{} has removed the key for {}.", &op_name, cur_r.get_name())));
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Topic(ref topic) => {
+            let cur_r = ctxt.grmap_mut(rid)?;
+            cur_r.set_topic(topic);
+            let msg = if cur_r.get_topic().len() == 0 {
+                format!("{} has cleared the topic of {}.", &op_name, cur_r.get_name())
+            } else {
+                format!("{} has set the topic of {} to \"{}\".", &op_name,
+                        cur_r.get_name(), cur_r.get_topic())
+            };
+            let env = Env::new(
+                End::Server,
+                End::Room(rid),
+                &Sndr::Info(&msg));
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Limit(n) => {
+            let cur_r = ctxt.grmap_mut(rid)?;
+            let msg = if n == 0 {
+                cur_r.set_limit(None);
+                format!("{} has removed the user limit for {}.", &op_name, cur_r.get_name())
+            } else {
+                cur_r.set_limit(Some(n as usize));
+                format!("{} has limited {} to {} users.", &op_name, cur_r.get_name(), n)
+            };
+            let env = Env::new(
+                End::Server,
+                End::Room(rid),
+                &Sndr::Info(&msg));
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Slow(n) => {
+            let cur_r = ctxt.grmap_mut(rid)?;
+            let msg = if n == 0 {
+                cur_r.set_slow(None);
+                format!("{} has turned off slow mode in {}.", &op_name, cur_r.get_name())
+            } else {
+                cur_r.set_slow(Some(Duration::from_secs(n as u64)));
+                format!("{} has put {} in slow mode: one post every {} s.",
+                        &op_name, cur_r.get_name(), n)
+            };
+            let env = Env::new(
+                End::Server,
+                End::Room(rid),
+                &Sndr::Info(&msg));
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Give(ref new_name) => {
+            let ouid = match op_target(ctxt, new_name) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
             };
             
             if ouid == ctxt.uid {
                 let env = Env::new(
                     End::Server,
                     End::User(ctxt.uid),
-                    &Sndr::Info("You are already the operator of this room."));
+                    &Sndr::Info("You are already the owner of this room."));
                 return Ok(Envs::new1(env));
             }
             
@@ -769,219 +1727,705 @@ fn do_op(ctxt: &mut Context, op: RcvOp)
                     &Sndr::Info(&format!("{} must be in the room to transfer ownership.", &ou_name)));
                 return Ok(Envs::new1(env));
             }
-            cur_r.set_op(ouid);
-            let dat: [&str; 2] = [&ou_name, cur_r.get_name()];
+            cur_r.set_owner(ouid);
+            let dat: [&str; 2] = [&ou_name, cur_r.get_name()];
+            let env = Env::new(
+                End::Server,
+                End::Room(rid),
+                &Sndr::Misc {
+                    what: "new_op",
+                    alt: &format!("{} is now the owner of {}.", &ou_name, cur_r.get_name()),
+                    data: &dat,
+                });
+            return Ok(Envs::new1(env));
+        },
+
+        RcvOp::Operator(ref uname) | RcvOp::Moderator(ref uname) | RcvOp::Demote(ref uname) => {
+            let new_role = match op {
+                RcvOp::Operator(_)  => Role::Operator,
+                RcvOp::Moderator(_) => Role::Moderator,
+                _                   => Role::Member,
+            };
+            
+            let ouid = match op_target(ctxt, uname) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            if let Some(v) = op_outrank(ctxt, ouid)? { return Ok(v); }
+            if new_role >= my_role {
+                let env = Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Err(&format!("You can only bestow ranks lower than {}.", my_role.name())));
+                return Ok(Envs::new1(env));
+            }
+            
+            let ou_name = {
+                let u = ctxt.gumap(ouid)?;
+                u.get_name().to_string()
+            };
+            
+            let cur_r = ctxt.grmap_mut(rid)?;
+            if cur_r.role_of(ouid) == new_role {
+                let env = Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info(&format!("{} is already a {} of {}.",
+                                         &ou_name, new_role.name(), cur_r.get_name())));
+                return Ok(Envs::new1(env));
+            }
+            cur_r.set_role(ouid, new_role);
+            let dat: [&str; 3] = [&ou_name, new_role.name(), cur_r.get_name()];
+            let env = Env::new(
+                End::Server,
+                End::Room(rid),
+                &Sndr::Misc {
+                    what: "role",
+                    alt: &format!("{} is now a {} of {}.", &ou_name, new_role.name(), cur_r.get_name()),
+                    data: &dat,
+                });
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Mute(ref uname) | RcvOp::Unmute(ref uname) => {
+            let muting = matches!(op, RcvOp::Mute(_));
+            let ouid = match op_target(ctxt, uname) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            if let Some(v) = op_outrank(ctxt, ouid)? { return Ok(v); }
+            
+            let ou_name = {
+                let u = ctxt.gumap(ouid)?;
+                u.get_name().to_string()
+            };
+            
+            let cur_r = ctxt.grmap_mut(rid)?;
+            let changed = match muting {
+                true  => cur_r.mute(ouid),
+                false => cur_r.unmute(ouid),
+            };
+            let env = match (changed, muting) {
+                (true, true) => Env::new(
+                    End::Server,
+                    End::Room(rid),
+                    &Sndr::Info(&format!("{} has muted {}.", &op_name, &ou_name))),
+                (true, false) => Env::new(
+                    End::Server,
+                    End::Room(rid),
+                    &Sndr::Info(&format!("{} has unmuted {}.", &op_name, &ou_name))),
+                (false, true) => Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info(&format!("{} is already muted.", &ou_name))),
+                (false, false) => Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info(&format!("{} is not muted.", &ou_name))),
+            };
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Invite(ref uname) => {
+            let ouid = match op_target(ctxt, uname) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+
+            let cur_r = match ctxt.rmap.get_mut(&ctxt.rid) {
+                None => { return Err(format!("do_op(r {}, u {}, {:?}): no Room {}",
+                                              ctxt.rid, ctxt.uid, &op, ctxt.rid));
+                },
+                Some(r) => r,
+            };
+            
+            if ouid == ctxt.uid {
+                let env = Env::new(
+                    End::Server,
+                    End::User(ctxt.uid),
+                    &Sndr::Info(&format!("You are already allowed in {}.", cur_r.get_name())));
+                return Ok(Envs::new1(env));
+            };
+            
+            let ou = match ctxt.umap.get_mut(&ouid) {
+                None => { return Err(format!("do_op(r {}, u {}, {:?}): no target User {}",
+                                              ctxt.rid, ctxt.uid, &op, ouid));
+                },
+                Some(u) => u,
+            };
+            
+            if cur_r.is_invited(&ouid, ou.get_idstr()) {
+                let env = Env::new(
+                    End::Server,
+                    End::User(ctxt.uid),
+                    &Sndr::Info(&format!("{} has already been invited to {}.",
+                                        ou.get_name(), cur_r.get_name())));
+            return Ok(Envs::new1(env));
+            };
+            cur_r.invite(Mark::new(ouid, ou.get_name(), &op_name));
+            
+            let inviter_env: Env;
+            if cur_r.get_users().contains(&ouid) {
+                inviter_env = Env::new(
+                    End::Server,
+                    End::User(ctxt.uid),
+                    &Sndr::Info(&format!("{} may now return to {} even when closed.",
+                                                ou.get_name(), cur_r.get_name())));
+                ou.deliver_msg(&Sndr::Info(&format!("You have been invited to return to {} even if it closes.",
+                                                cur_r.get_name())));
+            } else {
+                inviter_env = Env::new(
+                    End::Server,
+                    End::User(ctxt.uid),
+                    &Sndr::Info(&format!("You invite {} to join {}.", ou.get_name(), cur_r.get_name())));
+                ou.deliver_msg(&Sndr::Info(&format!("You have been invited to join {}.", cur_r.get_name())));
+            }
+            return Ok(Envs::new1(inviter_env));
+        },
+        
+        RcvOp::Unban(ref uname) | RcvOp::Uninvite(ref uname) => {
+            /* Bans and invitations outlive their subjects' connections, so
+            the target here needn't be logged on; if not, the record is
+            matched by collapsed name. */
+            let collapsed = ascollapse(uname);
+            if collapsed.len() == 0 {
+                let env = Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info("That cannot be anyone's user name."));
+                return Ok(Envs::new1(env));
+            }
+            let ouid = ctxt.gustr(&collapsed).unwrap_or(0);
+            
+            let cur_r = ctxt.grmap_mut(rid)?;
+            let env = match op {
+                RcvOp::Unban(_) => match cur_r.unban(ouid, &collapsed) {
+                    None => Env::new(
+                        End::Server,
+                        End::User(uid),
+                        &Sndr::Info(&format!("No one matching \"{}\" is banned from {}.",
+                                             &collapsed, cur_r.get_name()))),
+                    Some(m) => Env::new(
+                        End::Server,
+                        End::User(uid),
+                        &Sndr::Info(&format!("You have lifted the ban on {} (set by {}, {}).",
+                                             &m.name, &m.by, describe_age(unix_secs(m.when))))),
+                },
+                _ => match cur_r.uninvite(ouid, &collapsed) {
+                    None => Env::new(
+                        End::Server,
+                        End::User(uid),
+                        &Sndr::Info(&format!("No one matching \"{}\" has been invited to {}.",
+                                             &collapsed, cur_r.get_name()))),
+                    Some(m) => Env::new(
+                        End::Server,
+                        End::User(uid),
+                        &Sndr::Info(&format!("You have revoked the invitation of {} (set by {}, {}).",
+                                             &m.name, &m.by, describe_age(unix_secs(m.when))))),
+                },
+            };
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Bans | RcvOp::Invites => {
+            let r = ctxt.grmap(rid)?;
+            let (what, marks, empty_str, alt_head) = match op {
+                RcvOp::Bans => ("bans", r.get_bans(),
+                                format!("No one is banned from {}.", r.get_name()),
+                                format!("Banned from {}: ", r.get_name())),
+                _ => ("invites", r.get_invites(),
+                      format!("No one has been invited to {}.", r.get_name()),
+                      format!("Invited to {}: ", r.get_name())),
+            };
+            
+            if marks.len() == 0 {
+                let env = Env::new(
+                    End::Server,
+                    End::User(uid),
+                    &Sndr::Info(&empty_str));
+                return Ok(Envs::new1(env));
+            }
+            
+            let times: Vec<String> = marks.iter().map(|m| unix_secs(m.when).to_string()).collect();
+            let descs: Vec<String> = marks.iter().map(|m| {
+                format!("{} (by {}, {})", &m.name, &m.by, describe_age(unix_secs(m.when)))
+            }).collect();
+            let mut altstr = alt_head;
+            append_comma_delimited_list(&mut altstr, &descs);
+            
+            let mut dat: Vec<&str> = Vec::with_capacity(1 + 3 * marks.len());
+            dat.push(r.get_name());
+            for (m, t) in marks.iter().zip(times.iter()) {
+                dat.push(&m.name);
+                dat.push(&m.by);
+                dat.push(t);
+            }
+            
+            let env = Env::new(
+                End::Server,
+                End::User(uid),
+                &Sndr::Misc { what, data: &dat, alt: &altstr, });
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvOp::Kick(ref uname) => {
+            let ouid = match op_target(ctxt, uname) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            
+            if ouid == ctxt.uid {
+                let env = Env::new(
+                    End::Server,
+                    End::User(ctxt.uid),
+                    &Sndr::Info("If you want to leave, you can just join another room."));
+                return Ok(Envs::new1(env));
+            }
+            if let Some(v) = op_outrank(ctxt, ouid)? { return Ok(v); }
+            
+            let in_room = ctxt.grmap(rid)?.get_users().contains(&ouid);
+            if !in_room {
+                let ou = ctxt.gumap(ouid)?;
+                let env = Env::new(
+                    End::Server,
+                    End::User(ctxt.uid),
+                    &Sndr::Info(&format!("{} is not in this room.", ou.get_name())));
+                return Ok(Envs::new1(env));
+            }
+            
+            return op_eject(ctxt, ouid);
+        },
+        
+        RcvOp::Ban(ref uname) => {
+            let ouid = match op_target(ctxt, uname) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            
+            if ouid == ctxt.uid {
+                let env = Env::new(
+                    End::Server,
+                    End::User(ctxt.uid),
+                    &Sndr::Info("Bestowing the mantle of ownership on another and then leaving would be a more orderly transfer of power."
+                    ));
+                return Ok(Envs::new1(env));
+            }
+            if let Some(v) = op_outrank(ctxt, ouid)? { return Ok(v); }
+            
+            let (ku_name, ku_idstr) = {
+                let u = ctxt.gumap(ouid)?;
+                (u.get_name().to_string(), u.get_idstr().to_string())
+            };
+            
+            let in_room: bool;
+            {
+                let cur_r = ctxt.grmap_mut(rid)?;
+                if cur_r.is_banned(&ouid, &ku_idstr) {
+                    let env = Env::new(
+                        End::Server,
+                        End::User(uid),
+                        &Sndr::Info(&format!("{} is already banned from {}.",
+                                           &ku_name, cur_r.get_name())));
+                    return Ok(Envs::new1(env));
+                };
+                
+                cur_r.ban(Mark::new(ouid, &ku_name, &op_name));
+                in_room = cur_r.get_users().contains(&ouid);
+                
+                if !in_room {
+                    /* This case is easy because we only have to message the
+                    banner about his activity. */
+                    let env = Env::new(
+                        End::Server,
+                        End::User(uid),
+                        &Sndr::Info(&format!("You have banned {} from {}.", &ku_name, cur_r.get_name())));
+                    return Ok(Envs::new1(env));
+                }
+            }
+            
+            return op_eject(ctxt, ouid);
+        },
+    }
+}
+
+/* Wrap an announcement from the server itself (which can't be blocked)
+to everyone. */
+fn server_announcement(txt: &str) -> Env {
+    let dat: [&str; 2] = ["", txt];
+    Env::new(
+        End::Server,
+        End::All,
+        &Sndr::Misc {
+            what: "announce",
+            data: &dat,
+            alt: &format!("Announcement: {}", txt),
+        })
+}
+
+/* Hand an `Env` addressed to `End::All` to every `User` on the server.
+(`Room`s ignore these.)
+*/
+fn deliver_all(env: &Env, umap: &mut HashMap<u64, User>) {
+    for u in umap.values_mut() { u.deliver(env); }
+}
+
+/* Return the ID of the `Room` the `User` with the given ID is in. */
+fn room_of(rmap: &HashMap<u64, Room>, uid: u64) -> Option<u64> {
+    for (rid, r) in rmap.iter() {
+        if r.get_users().contains(&uid) { return Some(*rid); }
+    }
+    None
+}
+
+/* Look up the ID of the `Room` named as the target of an administrator
+command. As with `op_target()`, the `Err` holds the reply to send.
+*/
+fn adm_room(ctxt: &Context, room_name: &str) -> Result<u64, Env> {
+    let collapsed = ascollapse(room_name);
+    match ctxt.grstr(&collapsed) {
+        Some(n) => Ok(n),
+        None => {
+            let env = Env::new(
+                End::Server,
+                End::User(ctxt.uid),
+                &Sndr::Info(&format!("There is no Room \"{}\".", &collapsed)));
+            Err(env)
+        },
+    }
+}
+
+/* Disconnect the `User` with the given ID from the server, sending him
+`logout_msg` and telling whatever `Room` he was in why he left.
+*/
+fn adm_disconnect(ctxt: &mut Context, ouid: u64, logout_msg: &str, why: &str)
+-> Result<(), String> {
+    let mut ou = match ctxt.umap.remove(&ouid) {
+        None => { return Err(format!("adm_disconnect(r {}, u {}): no User {}", ctxt.rid, ctxt.uid, ouid)); },
+        Some(u) => u,
+    };
+    let _ = ctxt.ustr.remove(ou.get_idstr());
+    ou.logout(logout_msg);
+    
+    if let Some(orid) = room_of(ctxt.rmap, ouid) {
+        let r = ctxt.grmap_mut(orid)?;
+        r.leave(ouid);
+        let reason = format!("[ {} ]", why);
+        let dat: [&str; 2] = [ou.get_name(), &reason];
+        let env = Env::new(
+            End::Server,
+            End::Room(orid),
+            &Sndr::Misc {
+                what: "leave",
+                data: &dat,
+                alt: &format!("{} has been disconnected from the server ({}).", ou.get_name(), why),
+            });
+        r.enqueue(env);
+    }
+    Ok(())
+}
+
+/// In response to Msg::Admin(adm)
+fn do_admin(ctxt: &mut Context, cfg: &ServerConfig, adm: RcvAdm)
+-> Result<Envs, String> {
+    let uid = ctxt.uid;
+    let (uname, account) = {
+        let u = ctxt.gumap(uid)?;
+        (u.get_name().to_string(), u.get_admin().map(|s| s.to_string()))
+    };
+    
+    if let RcvAdm::Auth { name, password } = adm {
+        if let Some(v) = throttle_attempt(ctxt)? { return Ok(v); }
+        let msg = match cfg.admin(&name, &password) {
+            Some(a) => {
+                ctxt.srv.audit(&a.name, &uname, uid, "authenticated");
+                let mu = ctxt.gumap_mut(uid)?;
+                mu.set_admin(&name);
+                Sndr::Info("You are now a server administrator.")
+            },
+            None => {
+                ctxt.srv.audit(&name, &uname, uid, "FAILED to authenticate");
+                Sndr::Err("Administrator authentication failed.")
+            },
+        };
+        let env = Env::new(End::Server, End::User(uid), &msg);
+        return Ok(Envs::new1(env));
+    }
+    
+    let account = match account {
+        Some(a) => a,
+        None => {
+            ctxt.srv.audit("-", &uname, uid, &format!("DENIED {:?}", &adm));
+            let env = Env::new(
+                End::Server,
+                End::User(uid),
+                &Sndr::Err("You are not a server administrator."));
+            return Ok(Envs::new1(env));
+        },
+    };
+    
+    /* A reply to the acting administrator. */
+    let reply = |txt: &str| Ok(Envs::new1(Env::new(End::Server, End::User(uid), &Sndr::Info(txt))));
+    let is_ban = matches!(adm, RcvAdm::Ban { who: _, why: _ });
+    
+    match adm {
+        RcvAdm::Auth { name: _, password: _ } => { return Ok(Envs::new0()); },
+        
+        RcvAdm::Kill { who, why } | RcvAdm::Ban { who, why } => {
+            let ouid = match op_target(ctxt, &who) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            let why = if why.trim().len() == 0 { String::from("no reason given") } else { why };
+            
+            let (ou_name, addr) = {
+                let ou = ctxt.gumap_mut(ouid)?;
+                (ou.get_name().to_string(), ou.get_addr())
+            };
+            
+            let logout_msg = if is_ban {
+                let ip = match addr {
+                    Some(a) => addr_ip(&a),
+                    None => { return reply(&format!("The address of {} cannot be determined.", &ou_name)); },
+                };
+                ctxt.srv.audit(&account, &uname, uid,
+                               &format!("banned {} ({}): {}", &ou_name, &ip, &why));
+                ctxt.srv.bans.push((ip, Mark::new(ouid, &ou_name, &account)));
+                format!("You have been banned from the server: {}", &why)
+            } else {
+                ctxt.srv.audit(&account, &uname, uid, &format!("killed {}: {}", &ou_name, &why));
+                format!("You have been disconnected by an administrator: {}", &why)
+            };
+            adm_disconnect(ctxt, ouid, &logout_msg, &why)?;
+            
+            if ouid == uid { return Ok(Envs::new0()); }
+            return reply(&format!("You have {} {}.", if is_ban { "banned" } else { "killed" }, &ou_name));
+        },
+        
+        RcvAdm::Unban(addr) => {
+            let addr = addr.trim();
+            let n = ctxt.srv.bans.len();
+            ctxt.srv.bans.retain(|(a, _)| a != addr);
+            if n == ctxt.srv.bans.len() {
+                return reply(&format!("{} is not banned.", addr));
+            }
+            ctxt.srv.audit(&account, &uname, uid, &format!("unbanned {}", addr));
+            return reply(&format!("You have lifted the ban on {}.", addr));
+        },
+        
+        RcvAdm::Bans => {
+            let mut strs: Vec<String> = Vec::with_capacity(4 * ctxt.srv.bans.len());
+            let mut altstr = String::from("Banned from the server: ");
+            let mut listed: Vec<String> = Vec::with_capacity(ctxt.srv.bans.len());
+            for (addr, m) in ctxt.srv.bans.iter() {
+                let secs = unix_secs(m.when);
+                listed.push(format!("{} ({}, by {}, {})", addr, &m.name, &m.by, describe_age(secs)));
+                strs.push(addr.clone());
+                strs.push(m.name.clone());
+                strs.push(m.by.clone());
+                strs.push(secs.to_string());
+            }
+            if listed.len() == 0 { altstr.push_str("nobody"); }
+            append_comma_delimited_list(&mut altstr, &listed);
+            let dat: Vec<&str> = strs.iter().map(|x| x.as_str()).collect();
+            let env = Env::new(
+                End::Server,
+                End::User(uid),
+                &Sndr::Misc { what: "server_bans", data: &dat, alt: &altstr, });
+            return Ok(Envs::new1(env));
+        },
+        
+        RcvAdm::Close(room) => {
+            let orid = match adm_room(ctxt, &room) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            if orid == 0 { return reply("The lobby cannot be closed."); }
+            let r = ctxt.grmap_mut(orid)?;
+            if r.closed {
+                return reply(&format!("{} is already closed.", r.get_name()));
+            }
+            r.closed = true;
             let env = Env::new(
                 End::Server,
-                End::Room(rid),
-                &Sndr::Misc {
-                    what: "new_op",
-                    alt: &format!("{} is now the operator of {}.", &ou_name, cur_r.get_name()),
-                    data: &dat,
-                });
-            return Ok(Envs::new1(env));
+                End::Room(orid),
+                &Sndr::Info(&format!("{} has been closed by a server administrator.", r.get_name())));
+            r.enqueue(env);
+            let rname = r.get_name().to_string();
+            ctxt.srv.audit(&account, &uname, uid, &format!("closed {}", &rname));
+            if orid == ctxt.rid { return Ok(Envs::new0()); }
+            return reply(&format!("You have closed {}.", &rname));
         },
-
-        RcvOp::Invite(ref uname) => {
-            let collapsed = ascollapse(&uname);
-            if collapsed.len() == 0 {
-                let env = Env::new(
-                    End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info("That cannot be anyone's user name."));
-                return Ok(Envs::new1(env));
+        
+        RcvAdm::Destroy { room, why } => {
+            let why = if why.trim().len() == 0 { String::from("no reason given") } else { why };
+            /* A dormant room has nobody to evict; just forget it. */
+            if let Some(st) = ctxt.srv.dormant.remove(&ascollapse(&room)) {
+                ctxt.srv.audit(&account, &uname, uid, &format!("destroyed {}: {}", &st.name, &why));
+                return reply(&format!("You have destroyed {}.", &st.name));
             }
-            
-            let ouid = match ctxt.ustr.get(&collapsed) {
-                None => {
-                    let env = Env::new(
-                        End::Server,
-                        End::User(ctxt.uid),
-                        &Sndr::Info(&format!("No users matching the pattern \"{}\".", &collapsed)));
-                return Ok(Envs::new1(env));
-                },
-                Some(n) => *n,
-            };
-
-            let cur_r = match ctxt.rmap.get_mut(&ctxt.rid) {
-                None => { return Err(format!("do_op(r {}, u {}, {:?}): no Room {}",
-                                              ctxt.rid, ctxt.uid, &op, ctxt.rid));
-                },
-                Some(r) => r,
-            };
-            
-            if ouid == ctxt.uid {
-                let env = Env::new(
-                    End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info(&format!("You are already allowed in {}.", cur_r.get_name())));
-                return Ok(Envs::new1(env));
+            let orid = match adm_room(ctxt, &room) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
             };
+            if orid == 0 { return reply("The lobby cannot be destroyed."); }
             
-            let ou = match ctxt.umap.get_mut(&ouid) {
-                None => { return Err(format!("do_op(r {}, u {}, {:?}): no target User {}",
-                                              ctxt.rid, ctxt.uid, &op, ouid));
-                },
-                Some(u) => u,
+            let (rname, evicted) = {
+                let r = ctxt.grmap_mut(orid)?;
+                let evicted: Vec<u64> = r.get_users().to_vec();
+                for n in evicted.iter() { r.leave(*n); }
+                /* Keep it from being used again before it's cleaned up,
+                and from being saved when it is. */
+                r.destroy();
+                (r.get_name().to_string(), evicted)
             };
+            ctxt.srv.audit(&account, &uname, uid, &format!("destroyed {}: {}", &rname, &why));
             
-            if cur_r.is_invited(&ouid) {
+            let altstr = format!("{} has been destroyed by a server administrator: {}", &rname, &why);
+            let dat: [&str; 1] = [&rname];
+            for n in evicted.iter() {
+                let ou_name = match ctxt.umap.get_mut(n) {
+                    None => { continue; },
+                    Some(u) => {
+                        u.deliver_msg(&Sndr::Misc { what: "kick_you", data: &dat, alt: &altstr, });
+                        u.get_name().to_string()
+                    },
+                };
+                // If the lobby doesn't exist, the server can go ahead and crash.
+                let lobby = ctxt.rmap.get_mut(&0).unwrap();
+                lobby.join(*n);
+                let jdat: [&str; 2] = [&ou_name, lobby.get_name()];
                 let env = Env::new(
                     End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info(&format!("{} has already been invited to {}.",
-                                        ou.get_name(), cur_r.get_name())));
-            return Ok(Envs::new1(env));
-            };
-            cur_r.invite(ouid);
-            
-            let inviter_env: Env;
-            if cur_r.get_users().contains(&ouid) {
-                inviter_env = Env::new(
-                    End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info(&format!("{} may now return to {} even when closed.",
-                                                ou.get_name(), cur_r.get_name())));
-                ou.deliver_msg(&Sndr::Info(&format!("You have been invited to return to {} even if it closes.",
-                                                cur_r.get_name())));
-            } else {
-                inviter_env = Env::new(
-                    End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info(&format!("You invite {} to join {}.", ou.get_name(), cur_r.get_name())));
-                ou.deliver_msg(&Sndr::Info(&format!("You have been invited to join {}.", cur_r.get_name())));
+                    End::Room(0),
+                    &Sndr::Misc {
+                        what: "join",
+                        data: &jdat,
+                        alt: &format!("{} joins {}.", &ou_name, lobby.get_name()),
+                    });
+                lobby.enqueue(env);
             }
-            return Ok(Envs::new1(inviter_env));
+            if evicted.contains(&uid) { return Ok(Envs::new0()); }
+            return reply(&format!("You have destroyed {}.", &rname));
         },
         
-        RcvOp::Kick(ref uname) => {
-            let collapsed = ascollapse(&uname);
-            if collapsed.len() == 0 {
-                let env = Env::new(
-                    End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info("That cannot be anyone's user name."));
-                return Ok(Envs::new1(env));
+        RcvAdm::Rename { who, name } => {
+            let ouid = match op_target(ctxt, &who) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            let new_idstr = ascollapse(&name);
+            if new_idstr.len() == 0 || name.len() > cfg.max_user_name_length {
+                return reply(&format!("\"{}\" is not a valid name.", &name));
+            }
+            if let Some(n) = ctxt.gustr(&new_idstr) {
+                if n != ouid {
+                    return reply(&format!("There is already a user named \"{}\".", &name));
+                }
+            }
+            if let Some(a) = ctxt.srv.accounts.get(&new_idstr) {
+                let ou = ctxt.gumap(ouid)?;
+                if ou.get_account() != Some(new_idstr.as_str()) {
+                    return reply(&format!("The name \"{}\" is registered to another account.", &a.name));
+                }
             }
             
-            let ouid = match ctxt.ustr.get(&collapsed) {
-                None => {
-                    let env = Env::new(
-                        End::Server,
-                        End::User(ctxt.uid),
-                        &Sndr::Info(&format!("No users matching the pattern \"{}\".", &collapsed)));
-                return Ok(Envs::new1(env));
-                },
-                Some(n) => *n,
+            let (old_name, old_idstr) = {
+                let ou = ctxt.gumap_mut(ouid)?;
+                let old = (ou.get_name().to_string(), ou.get_idstr().to_string());
+                ou.set_name(&name);
+                old
             };
+            let _ = ctxt.ustr.remove(&old_idstr);
+            ctxt.ustr.insert(new_idstr, ouid);
+            restore_blocks(ctxt.umap, &ctxt.srv.accounts, ouid);
+            ctxt.srv.audit(&account, &uname, uid, &format!("renamed {} to {}", &old_name, &name));
             
-            if ouid == ctxt.uid {
+            if let Some(orid) = room_of(ctxt.rmap, ouid) {
+                let r = ctxt.grmap_mut(orid)?;
+                let dat: [&str; 2] = [&old_name, &name];
                 let env = Env::new(
                     End::Server,
-                    End::User(ctxt.uid),
-                    &Sndr::Info("Bestowing the operator mantle on another and then leaving would be a more orderly transfer of power."
-                    ));
-                return Ok(Envs::new1(env));
+                    End::Room(orid),
+                    &Sndr::Misc {
+                        what: "name",
+                        data: &dat,
+                        alt: &format!("{} is now known as {}.", &old_name, &name),
+                    });
+                r.enqueue(env);
             }
-            
-            let ku = match ctxt.umap.get_mut(&ouid) {
-                None => { return Err(format!("do_op(r {}, u {}, {:?}): no target User {}",
-                                             ctxt.rid, ctxt.uid, &op, ouid));
-                },
-                Some(u) => u,
+            return reply(&format!("You have renamed {} to {}.", &old_name, &name));
+        },
+        
+        RcvAdm::Owner { room, who } => {
+            let orid = match adm_room(ctxt, &room) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
             };
+            if orid == 0 { return reply("Nobody can own the lobby."); }
+            let ouid = match op_target(ctxt, &who) {
+                Ok(n) => n,
+                Err(env) => { return Ok(Envs::new1(env)); },
+            };
+            let ou_name = ctxt.gumap(ouid)?.get_name().to_string();
             
-            let in_room: bool;
-            let mut cur_room_name = String::new();
-            
-            {
-                let cur_r = match ctxt.rmap.get_mut(&ctxt.rid) {
-                    None => { return Err(format!("do_op(r {}, u {}, {:?}): no Room {}",
-                                                 ctxt.rid, ctxt.uid, &op, ctxt.rid));
-                    },
-                    Some(r) => r,
-                };
-            
-                if cur_r.is_banned(&ouid) {
-                    let env = Env::new(
-                        End::Server,
-                        End::User(ctxt.uid),
-                        &Sndr::Info(&format!("{} is already banned from {}.",
-                                           ku.get_name(), cur_r.get_name())));
-                    return Ok(Envs::new1(env));
-                };
-            
-                cur_r.ban(ouid);
-                in_room = cur_r.get_users().contains(&ouid);
-                
-                if !in_room {
-                    /* This case is easy because we only have to message the
-                    banner about his activity.
-                    */
-                    if !cur_r.get_users().contains(&ouid) {
-                        let env = Env::new(
-                            End::Server,
-                            End::User(ctxt.uid),
-                            &Sndr::Info(&format!("You have banned {} from {}.", ku.get_name(), cur_r.get_name())));
-                        return Ok(Envs::new1(env));
-                        /* If the kickee is _not_ in the room, this function
-                        returns now. All the rest of the Op::Kick match arm,
-                        even after the closing of the conditionals and
-                        reference-dropping scopes, involves the case where
-                        the kickee _is_ in the current room.
-                        */
-                    }
-                } else {
-                    /* This case is tougher because it involves
-                      * messaging the kicked user
-                      * moving the kicked user
-                      * messaging the room
-                      * messaging the Lobby that he's joined it
-                    
-                    It requires careful dancing around &mut lifetimes.
-                    */
-                    
-                    let altstr = format!("You have been kicked from {}.", cur_r.get_name());
-                    let dat: [&str; 1] = [cur_r.get_name()];
-                    let to_kicked = Sndr::Misc { what: "kick_you", alt: &altstr, data: &dat, };
-                    ku.deliver_msg(&to_kicked);                    
-                    cur_r.leave(ouid);
-            
-                    cur_room_name = cur_r.get_name().to_string();
-                }
-                
-                /* Mutable reference to ctxt.rmap contents (&mut cur_r) drops
-                here, allowing us to get a mutable reference to the lobby to
-                add the kicked user back in there.
-                 */
+            let r = ctxt.grmap_mut(orid)?;
+            let rname = r.get_name().to_string();
+            if !r.get_users().contains(&ouid) {
+                return reply(&format!("{} must be in {} to own it.", &ou_name, &rname));
             }
-            
-            // If the lobby doesn't exist, the server can go ahead and crash.
-            let lobby = ctxt.rmap.get_mut(&0).unwrap();
-            lobby.join(ouid);
-            let dat: [&str; 2] = [ku.get_name(), lobby.get_name()];
-            let to_lobby = Env::new(
+            r.set_owner(ouid);
+            let dat: [&str; 2] = [&ou_name, &rname];
+            let env = Env::new(
                 End::Server,
-                End::Room(ctxt.rid),
+                End::Room(orid),
                 &Sndr::Misc {
-                    what: "join",
+                    what: "new_op",
                     data: &dat,
-                    alt: &format!("{} joins {}.", ku.get_name(), lobby.get_name()),
+                    alt: &format!("{} is now the owner of {}.", &ou_name, &rname),
                 });
-            lobby.enqueue(to_lobby);
-            
-            let dat: [&str; 2] = [ku.get_name(), &cur_room_name];
+            r.enqueue(env);
+            ctxt.srv.audit(&account, &uname, uid, &format!("gave {} to {}", &rname, &ou_name));
+            if orid == ctxt.rid { return Ok(Envs::new0()); }
+            return reply(&format!("{} is now the owner of {}.", &ou_name, &rname));
+        },
+        
+        RcvAdm::Broadcast(text) => {
+            ctxt.srv.audit(&account, &uname, uid, &format!("broadcast: {}", &text));
+            /* Coming from a `User`, this will respect blocks. */
+            let dat: [&str; 2] = [&uname, &text];
             let env = Env::new(
-                End::Server,
-                End::Room(ctxt.rid),
+                End::User(uid),
+                End::All,
                 &Sndr::Misc {
-                    what: "kick_other",
+                    what: "announce",
                     data: &dat,
-                    alt: &format!("{} has been kicked from {}.", ku.get_name(), &cur_room_name),
+                    alt: &format!("Announcement from {}: {}", &uname, &text),
                 });
-            
             return Ok(Envs::new1(env));
         },
+        
+        RcvAdm::Shutdown { secs, why } => {
+            ctxt.srv.audit(&account, &uname, uid,
+                           &format!("scheduled shutdown in {} s: {}", secs, &why));
+            let txt = ctxt.srv.schedule_shutdown(secs as u64, why.trim());
+            return Ok(Envs::new1(server_announcement(&txt)));
+        },
+        
+        RcvAdm::Reload => {
+            /* This has to happen back in `main()`, where the configuration
+            isn't borrowed. */
+            ctxt.srv.audit(&account, &uname, uid, "requested configuration reload");
+            ctxt.srv.reload = Some(uid);
+            return Ok(Envs::new0());
+        },
+        
+        RcvAdm::Cancel => {
+            if ctxt.srv.shutdown.take().is_none() {
+                return reply("There is no shutdown scheduled.");
+            }
+            ctxt.srv.audit(&account, &uname, uid, "canceled shutdown");
+            return Ok(Envs::new1(server_announcement("The scheduled shutdown has been called off.")));
+        },
     }
 }
 
@@ -995,6 +2439,7 @@ It also performs some various housekeeping, like displaying any messages
 the op mantle on another user if the op leaves.
 */
 
+#[allow(clippy::too_many_arguments)]
 fn process_room(
     rid: u64,
     current_time: Instant,
@@ -1002,6 +2447,7 @@ fn process_room(
     ustr_map: &mut HashMap<String, u64>,
     room_map: &mut HashMap<u64, Room>,
     rstr_map: &mut HashMap<String, u64>,
+    srv: &mut ServerState,
     cfg: &ServerConfig
 ) -> Result<(), String> {
     let mut uid_list: SmallVec<[u64; ROOM_SIZE]>;
@@ -1015,12 +2461,13 @@ fn process_room(
     }
     
     let mut ctxt = Context {
-        rid: rid,
+        rid,
         uid: 0,
         umap: user_map,
         ustr: ustr_map,
         rmap: room_map,
         rstr: rstr_map,
+        srv,
     };
     
     let mut envz: Envs = Envs::new0();
@@ -1085,12 +2532,18 @@ fn process_room(
             Rcvr::Text { who: _, lines: l } => do_text(&mut ctxt, l),
             Rcvr::Priv { who, text }        => do_priv(&mut ctxt, who, text),
             Rcvr::Name(new_candidate)       => do_name(&mut ctxt, cfg, new_candidate),
-            Rcvr::Join(room_name)           => do_join(&mut ctxt, cfg, room_name),
+            Rcvr::Join(room_name)           => do_join(&mut ctxt, cfg, room_name, None),
+            Rcvr::JoinKey { room, key }     => do_join(&mut ctxt, cfg, room, Some(key)),
             Rcvr::Block(user_name)          => do_block(&mut ctxt, user_name),
             Rcvr::Unblock(user_name)        => do_unblock(&mut ctxt, user_name),
+            Rcvr::Register(password)        => do_register(&mut ctxt, password),
+            Rcvr::Identify { name, password } => do_identify(&mut ctxt, cfg, name, password),
+            Rcvr::Memo { who, text }        => do_memo(&mut ctxt, cfg, who, text),
+            Rcvr::Away(msg)                 => do_away(&mut ctxt, msg),
             Rcvr::Logout(salutation)        => do_logout(&mut ctxt, salutation),
-            Rcvr::Query{ what, arg }        => do_query(&mut ctxt, what, arg),
+            Rcvr::Query{ what, arg }        => do_query(&mut ctxt, cfg, what, arg),
             Rcvr::Op(op)                    => do_op(&mut ctxt, op),
+            Rcvr::Admin(adm)                => do_admin(&mut ctxt, cfg, adm),
             _ => { /* Other patterns require no response. */ Ok(Envs::new0()) },
         };
         
@@ -1109,7 +2562,7 @@ fn process_room(
     }
     
     for (uid, errmsg) in logouts.iter() {
-        if let Some(mut mu) = ctxt.umap.remove(&uid) {
+        if let Some(mut mu) = ctxt.umap.remove(uid) {
             let _ = ctxt.ustr.remove(mu.get_idstr());
             let msg = Sndr::Logout(errmsg);
             mu.deliver_msg(&msg);
@@ -1128,20 +2581,24 @@ fn process_room(
         }
     }
     
-    // Change room operator if current op is no longer in room.
+    // Change room owner if current owner is no longer in room.
     // (But obviously not for the lobby.)
     
     if rid != 0 {
         let mr = ctxt.rmap.get_mut(&rid).unwrap();
-        let op_id = mr.get_op();
-        let op_still_here = mr.get_users().contains(&op_id);
-        if !op_still_here {
-            if let Some(pnid) = mr.get_users().get(0) {
-                if let Some(u) = ctxt.umap.get(pnid) {
-                    let nid = *pnid;
-                    mr.set_op(nid);
+        let owner_id = mr.get_owner();
+        let owner_still_here = mr.get_users().contains(&owner_id);
+        if !owner_still_here {
+            if let Some(nid) = mr.successor() {
+                if let Some(u) = ctxt.umap.get(&nid) {
+                    mr.set_owner(nid);
+                    let dat: [&str; 2] = [u.get_name(), mr.get_name()];
                     let env = Env::new(End::Server, End::Room(rid),
-                        &Sndr::Info(&format!("{} is now the Room operator.", u.get_name())));
+                        &Sndr::Misc {
+                            what: "new_op",
+                            data: &dat,
+                            alt: &format!("{} is now the owner of {}.", u.get_name(), mr.get_name()),
+                        });
                     envz.as_mut().push(env);
                 }
             }
@@ -1153,7 +2610,10 @@ fn process_room(
         for (uid, _) in logouts.drain(..) { r.leave(uid); }
         r.deliver_inbox(ctxt.umap);
         for env in envz.as_ref() {
-            r.deliver(env, ctxt.umap);
+            match env.dest {
+                End::All => deliver_all(env, ctxt.umap),
+                _ => r.deliver(env, ctxt.umap),
+            }
         }
         uid_list.clear();
         uid_list.extend_from_slice(r.get_users());
@@ -1168,46 +2628,296 @@ fn process_room(
 }
 
 /** When a user joins with a name that `ascollapse()`s to a user who is
-already joined, this generates them a generic (but unique) name.
+already joined (or to a registered name), this generates them a generic
+(but unique, and unregistered) name.
 */
-fn gen_name(init_count: u64, map: &HashMap<String, u64>) -> String {
+fn gen_name(init_count: u64, map: &HashMap<String, u64>,
+            accounts: &HashMap<String, Account>) -> String {
     let mut n = init_count;
     loop {
         let new_name = format!("user{}", n);
-        if map.get(&new_name) == None {
+        if map.get(&new_name).is_none() && !accounts.contains_key(&new_name) {
             return new_name;
         }
-        n = n + 1;
+        n += 1;
     }
 }
 
 /** Write pidfile. This may get formalized or configurizable eventually;
 right now it just makes it easier to stop the server.
 */
-fn write_pid() -> std::io::Result<()> {
+fn write_pid(path: &str) -> std::io::Result<()> {
     use std::fs::File;
     use std::io::Write;
     let pidstr = format!("{}", std::process::id());
-    let mut pidf = File::create("d.pid")?;
+    let mut pidf = File::create(path)?;
     pidf.write_all(pidstr.as_bytes())?;
     return pidf.sync_all()
 }
 
-fn main() {
-    if let Err(e) = write_pid() {
-        println!("Error writing pidfile: {}", e);
+/* After a reload changes the admin accounts, demote any `User`s who
+authenticated as accounts that have since been removed or had their
+passwords changed. */
+fn revoke_stale_admins(
+    old_admins: &[AdminAccount],
+    cfg: &ServerConfig,
+    srv: &mut ServerState,
+    user_map: &mut HashMap<u64, User>
+) {
+    for u in user_map.values_mut() {
+        let name = match u.get_admin() {
+            Some(name) => name.to_string(),
+            None => { continue; },
+        };
+        let still = cfg.admins.iter()
+            .any(|a| a.name == name && old_admins.contains(a));
+        if !still {
+            srv.audit(&name, u.get_name(), u.get_id(), "revoked by configuration reload");
+            u.clear_admin();
+            u.deliver_msg(&Sndr::Info("Your server administrator account has changed; you must authenticate again."));
+        }
+    }
+}
+
+/** Reread the configuration file and apply what can be applied while
+running, logging (and telling the requesting administrator, if any) what
+changed and what will need a restart.
+*/
+fn reload_config(
+    cfg: &mut ServerConfig,
+    cmdline: &CmdLine,
+    srv: &mut ServerState,
+    user_map: &mut HashMap<u64, User>,
+    by: Option<u64>
+) {
+    let report = match ServerConfig::reread(cmdline.config.as_deref()) {
+        Err(e) => format!("Unable to reload configuration: {}", &e),
+        Ok(mut new) => {
+            cmdline.apply(&mut new);
+            let old_admins = cfg.admins.clone();
+            let (changed, restart) = cfg.apply(new);
+            log::set_max_level(cfg.log_level);
+            srv.audit_path = cfg.audit_log.clone();
+            if changed.contains(&"welcome") {
+                deliver_all(&server_announcement(&cfg.welcome), user_map);
+            }
+            if changed.contains(&"admins") {
+                revoke_stale_admins(&old_admins, cfg, srv, user_map);
+            }
+            
+            let mut report = String::from("Configuration reloaded. ");
+            if changed.len() == 0 {
+                report.push_str("Nothing changed.");
+            } else {
+                report.push_str("Changed: ");
+                append_comma_delimited_list(&mut report, &changed);
+                report.push('.');
+            }
+            if restart.len() > 0 {
+                report.push_str(" Changes to ");
+                append_comma_delimited_list(&mut report, &restart);
+                report.push_str(" require a restart.");
+            }
+            report
+        },
+    };
+    warn!("{}", &report);
+    if let Some(uid) = by {
+        if let Some(u) = user_map.get_mut(&uid) { u.deliver_msg(&Sndr::Info(&report)); }
+    }
+}
+
+/** Write the server's durable state to `cfg.state_file`, logging (but
+otherwise ignoring) any failure.
+*/
+fn save_state(cfg: &ServerConfig, srv: &ServerState, room_map: &HashMap<u64, Room>) {
+    match srv.snapshot(room_map).save(&cfg.state_file) {
+        Ok(()) => { debug!("Saved state to {}", &cfg.state_file); },
+        Err(e) => { warn!("Error saving state: {}", &e); },
+    }
+}
+
+/** Log everyone off with `msg`, giving their clients up to
+`cfg.shutdown_flush` to receive it, then clean up after ourselves.
+*/
+fn shut_down(
+    user_map: &mut HashMap<u64, User>,
+    room_map: &HashMap<u64, Room>,
+    srv: &ServerState,
+    cfg: &ServerConfig,
+    msg: &str
+) {
+    warn!("Shutting down: {}", msg);
+    let logout = Sndr::Logout(msg);
+    for u in user_map.values_mut() { u.deliver_msg(&logout); }
+    
+    let start = Instant::now();
+    loop {
+        for u in user_map.values_mut() { u.nudge(); }
+        let done = user_map.values().all(|u| u.pending_bytes() == 0 || u.has_errors());
+        if done || start.elapsed() > cfg.shutdown_flush { break; }
+        thread::sleep(FLUSH_TICK);
+    }
+    for u in user_map.values_mut() { u.close(); }
+    
+    save_state(cfg, srv, room_map);
+    
+    if let Err(e) = std::fs::remove_file(&cfg.pidfile) {
+        warn!("Error removing pidfile {}: {}", &cfg.pidfile, &e);
+    }
+}
+
+/* Settings given on the command line. These take precedence over the
+config file, including when it's reloaded.
+*/
+struct CmdLine {
+    config:    Option<String>,
+    address:   Option<String>,
+    log_file:  Option<String>,
+    log_level: Option<simplelog::LevelFilter>,
+    pidfile:   Option<String>,
+}
+
+impl CmdLine {
+    fn apply(&self, cfg: &mut ServerConfig) {
+        if let Some(x) = &self.address  { cfg.address  = x.clone(); }
+        if let Some(x) = &self.log_file { cfg.log_file = x.clone(); }
+        if let Some(x) = self.log_level { cfg.log_level = x; }
+        if let Some(x) = &self.pidfile  { cfg.pidfile  = x.clone(); }
+    }
+}
+
+/** Read command line options and configuration file. */
+fn configure() -> (ServerConfig, CmdLine) {
+    let opts = clap::App::new("greld")
+        .max_term_width(80)
+        .version("0.1")
+        .author("Dan Hill <daniel.s.hill@gmail.com>")
+        .about("grel chat server")
+        .arg(clap::Arg::with_name("config")
+            .short("c").long("config")
+            .value_name("FILE")
+            .help("use an alternate configuration file")
+            .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("address")
+            .short("a").long("address")
+            .value_name("IP:PORT")
+            .help("listen on IP:PORT")
+            .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("log_file")
+            .short("l").long("log-file")
+            .value_name("FILE")
+            .help("write the log to FILE")
+            .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("log_level")
+            .short("v").long("log-level")
+            .value_name("LEVEL")
+            .help("0 (off) through 5 (trace), or off, error, warn, info, debug, or trace")
+            .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("pidfile")
+            .short("p").long("pidfile")
+            .value_name("FILE")
+            .help("write the server's process ID to FILE")
+            .takes_value(true),
+        )
+        .arg(clap::Arg::with_name("write")
+            .short("g").long("generate-default")
+            .help("generate a new default configuration file")
+            .takes_value(false),
+        )
+        .arg(clap::Arg::with_name("check")
+            .short("k").long("check-config")
+            .help("check the configuration and exit without starting")
+            .takes_value(false),
+        )
+        .get_matches();
+    
+    if opts.is_present("write") {
+        match ServerConfig::generate() {
+            Ok(dir) => {
+                println!("Default configuration file written to {}", &dir);
+                std::process::exit(0);
+            },
+            Err(e) => {
+                println!("{}", e);
+                std::process::exit(2);
+            },
+        }
+    }
+    
+    let log_level = match opts.value_of("log_level").map(parse_log_level) {
+        None => None,
+        Some(Ok(x)) => Some(x),
+        Some(Err(e)) => {
+            println!("{}", e);
+            std::process::exit(1);
+        },
+    };
+    let cmdline = CmdLine {
+        config:    opts.value_of("config")  .map(String::from),
+        address:   opts.value_of("address") .map(String::from),
+        log_file:  opts.value_of("log_file").map(String::from),
+        log_level,
+        pidfile:   opts.value_of("pidfile") .map(String::from),
+    };
+    
+    let mut cfg = match ServerConfig::configure(cmdline.config.as_deref()) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("Configuration error: {}", e);
+            std::process::exit(1);
+        },
     };
+    cmdline.apply(&mut cfg);
+    if let Err(e) = cfg.validate() {
+        println!("Configuration error: {}", e);
+        std::process::exit(1);
+    }
+    
+    if opts.is_present("check") {
+        println!("Configuration OK: {:?}", &cfg);
+        std::process::exit(0);
+    }
     
-    let cfg: ServerConfig = ServerConfig::configure();
+    return (cfg, cmdline);
+}
+
+fn main() {
+    let (mut cfg, cmdline) = configure();
     println!("Configuration: {:?}", &cfg);
-    WriteLogger::init(cfg.log_level, simplelog::Config::default(),
+    if let Err(e) = write_pid(&cfg.pidfile) {
+        println!("Error writing pidfile: {}", e);
+    };
+    /* The logger itself lets everything through, so the level can be
+    changed on a reload by `log::set_max_level()`. */
+    WriteLogger::init(simplelog::LevelFilter::Trace, simplelog::Config::default(),
                       std::fs::File::create(&cfg.log_file).unwrap()).unwrap();
+    log::set_max_level(cfg.log_level);
     let listen_addr = cfg.address.clone();
     
     let mut user_map: HashMap<u64, User> = HashMap::new();
     let mut ustr_map: HashMap<String, u64> = HashMap::new();
     let mut room_map: HashMap<u64, Room> = HashMap::new();
     let mut rstr_map: HashMap<String, u64> = HashMap::new();
+    let mut srv = ServerState::new(&cfg);
+    match Snapshot::load(&cfg.state_file) {
+        Ok(Some(snap)) => {
+            debug!("Restoring state saved at {} from {}", snap.saved, &cfg.state_file);
+            srv.restore(snap);
+        },
+        Ok(None) => { debug!("No saved state in {}; starting fresh.", &cfg.state_file); },
+        Err(e) => {
+            /* Refuse to start rather than overwrite state we can't read. */
+            println!("Error loading saved state: {}", &e);
+            warn!("Error loading saved state: {}", &e);
+            let _ = std::fs::remove_file(&cfg.pidfile);
+            std::process::exit(1);
+        },
+    }
     
     /* We set the lobby's uid to be 0, because no user will have a
        uid less than 100.
@@ -1219,15 +2929,55 @@ fn main() {
     let (usender, urecvr) = mpsc::channel::<User>();
     thread::spawn(move || { listen(listen_addr, usender); });
     
+    let stop = Arc::new(AtomicBool::new(false));
+    let hup = Arc::new(AtomicBool::new(false));
+    for (sig, flag) in [(signal_hook::SIGTERM, &stop), (signal_hook::SIGINT, &stop),
+                        (signal_hook::SIGHUP, &hup)].iter() {
+        if let Err(e) = signal_hook::flag::register(*sig, Arc::clone(flag)) {
+            warn!("Unable to register handler for signal {}: {}", sig, &e);
+        }
+    }
+    
     let mut now: Instant;
+    let mut last_save = Instant::now();
     
     loop {
         now = Instant::now();
-        let mut roomz: Vec<u64> = room_map.keys().map(|k| *k).collect();
+        
+        if now.duration_since(last_save) >= cfg.save_interval {
+            srv.prune_memos(cfg.memo_expiry);
+            save_state(&cfg, &srv, &room_map);
+            last_save = now;
+        }
+        
+        if stop.load(Ordering::SeqCst) {
+            shut_down(&mut user_map, &room_map, &srv, &cfg, &cfg.shutdown_message);
+            return;
+        }
+        if let Some((when, why)) = &srv.shutdown {
+            if now >= *when {
+                let msg = if why.len() > 0 {
+                    format!("{} ({})", &cfg.shutdown_message, why)
+                } else {
+                    cfg.shutdown_message.clone()
+                };
+                shut_down(&mut user_map, &room_map, &srv, &cfg, &msg);
+                return;
+            }
+        }
+        if let Some(txt) = srv.countdown(now) {
+            deliver_all(&server_announcement(&txt), &mut user_map);
+        }
+        if hup.swap(false, Ordering::SeqCst) || srv.reload.is_some() {
+            let by = srv.reload.take();
+            reload_config(&mut cfg, &cmdline, &mut srv, &mut user_map, by);
+        }
+        
+        let mut roomz: Vec<u64> = room_map.keys().copied().collect();
         for rid in roomz.drain(..) {
             let rnum = room_map.len();
             match process_room(rid, now, &mut user_map, &mut ustr_map,
-                               &mut room_map, &mut rstr_map, &cfg) {
+                               &mut room_map, &mut rstr_map, &mut srv, &cfg) {
                 Ok(()) => {},
                 Err(e) => { warn!("process_room({}, ...) returned error: {}", rid, &e); },
             }
@@ -1245,57 +2995,79 @@ fn main() {
                     }
                 }
                 if remove {
-                    let _ = room_map.remove(&rid);
+                    /* Rooms with registered owners (and restored rooms
+                    nobody managed to get into) sleep instead of vanishing,
+                    unless they've been destroyed. */
+                    if let Some(r) = room_map.remove(&rid) {
+                        let st = r.snapshot();
+                        if !r.is_destroyed() && (st.has_owner() || !r.was_visited()) {
+                            srv.dormant.insert(r.get_idstr().to_string(), st);
+                        }
+                    }
                 }
             }
             
         }
         
-        match urecvr.try_recv() {
-            Ok(mut u) => {
-                debug!("Accepting user {}: {}", u.get_id(), u.get_name());
-                u.deliver_msg(&Sndr::Info(&cfg.welcome));
-                
-                let mut rename: Option<String> = None;
-                if u.get_idstr().len() == 0 {
-                    rename = Some(String::from("Your name does not have enough whitespace characters."));
-                } else if u.get_name().len() > cfg.max_user_name_length {
-                    rename = Some(format!("Your name cannot be longer than {} bytes.", cfg.max_user_name_length));
-                } else {
-                    let maybe_same_name = ustr_map.get(u.get_idstr());
-                    if let Some(user_n) = maybe_same_name {
-                        rename = Some(format!("Name \"{}\" exists.", user_map.get(user_n).unwrap().get_name()));
-                    }
-                }
-                
-                if let Some(err_msg) = rename {
-                    let new_name = gen_name(u.get_id(), &ustr_map);
-                    let msg = Sndr::Err(&err_msg);
-                    u.deliver_msg(&msg);
-                    let old_name = u.get_name().to_string();
-                    let dat: [&str; 2] = [&old_name, &new_name];
-                    let altstr = format!("You are now known as \"{}\".", &new_name);
-                    let msg = Sndr::Misc { what: "name", data: &dat, alt: &altstr, };
-                    u.set_name(&new_name);
-                    u.deliver_msg(&msg);
+        /* Connections from banned addresses are refused and dropped here. */
+        let accepted = match urecvr.try_recv() {
+            Ok(mut u) => match u.get_addr() {
+                Some(addr) if srv.is_banned(&addr) => {
+                    debug!("Refusing banned address {}", &addr);
+                    u.logout("You are banned from this server.");
+                    None
+                },
+                _ => Some(u),
+            },
+            Err(_) => None,
+        };
+        if let Some(mut u) = accepted {
+            debug!("Accepting user {}: {}", u.get_id(), u.get_name());
+            u.deliver_msg(&Sndr::Info(&cfg.welcome));
+            
+            let mut rename: Option<String> = None;
+            if u.get_idstr().len() == 0 {
+                rename = Some(String::from("Your name does not have enough whitespace characters."));
+            } else if u.get_name().len() > cfg.max_user_name_length {
+                rename = Some(format!("Your name cannot be longer than {} bytes.", cfg.max_user_name_length));
+            } else {
+                let maybe_same_name = ustr_map.get(u.get_idstr());
+                if let Some(user_n) = maybe_same_name {
+                    rename = Some(format!("Name \"{}\" exists.", user_map.get(user_n).unwrap().get_name()));
+                } else if let Some(a) = srv.accounts.get(u.get_idstr()) {
+                    rename = Some(format!("Name \"{}\" is registered; identify to use it.", &a.name));
                 }
+            }
+            
+            if let Some(err_msg) = rename {
+                let new_name = gen_name(u.get_id(), &ustr_map, &srv.accounts);
+                let msg = Sndr::Err(&err_msg);
+                u.deliver_msg(&msg);
+                let old_name = u.get_name().to_string();
+                let dat: [&str; 2] = [&old_name, &new_name];
+                let altstr = format!("You are now known as \"{}\".", &new_name);
+                let msg = Sndr::Misc { what: "name", data: &dat, alt: &altstr, };
+                u.set_name(&new_name);
+                u.deliver_msg(&msg);
+            }
 
-                let dat: [&str; 2] = [u.get_name(), &cfg.lobby_name];
-                let env = Env::new(
-                    End::Server,
-                    End::Room(0),
-                    &Sndr::Misc{
-                        what: "join",
-                        data: &dat,
-                        alt: &format!("{} joins {}.", u.get_name(), &cfg.lobby_name),
-                });
-                let lobby = room_map.get_mut(&0).unwrap();
-                lobby.join(u.get_id());
-                lobby.enqueue(env);
-                ustr_map.insert(u.get_idstr().to_string(), u.get_id());
-                user_map.insert(u.get_id(), u);
-            },
-            Err(_) => {},
+            let dat: [&str; 2] = [u.get_name(), &cfg.lobby_name];
+            let env = Env::new(
+                End::Server,
+                End::Room(0),
+                &Sndr::Misc{
+                    what: "join",
+                    data: &dat,
+                    alt: &format!("{} joins {}.", u.get_name(), &cfg.lobby_name),
+            });
+            let lobby = room_map.get_mut(&0).unwrap();
+            lobby.join(u.get_id());
+            lobby.enqueue(env);
+            for env in srv.take_memos(&u).as_ref() { u.deliver(env); }
+            let uid = u.get_id();
+            ustr_map.insert(u.get_idstr().to_string(), uid);
+            user_map.insert(uid, u);
+            restore_blocks(&mut user_map, &srv.accounts, uid);
         }
         
         let loop_time = Instant::now().duration_since(now);
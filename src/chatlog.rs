@@ -0,0 +1,116 @@
+/*!
+Plain-text logs of the chat the client displays.
+
+A `ChatLog` writes lines to files in a directory, one file per `Room` or
+private correspondent per day, named like `room-lobby-2021-02-14.log` or
+`priv-jose-2021-02-14.log`. (Names are `ascollapse()`d, with anything that
+might be awkward in a file name replaced by `_`.) Each line is written with
+the time it was shown, and with no styling.
+
+Files are opened (in append mode) the first time they're needed and kept
+open until the day changes.
+*/
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local, NaiveDate};
+
+use crate::user::ascollapse;
+
+pub struct ChatLog {
+    dir: PathBuf,
+    /// open files, by the part of their names before the date
+    files: HashMap<String, (NaiveDate, File)>,
+}
+
+/* Return `name` collapsed and safe to use in a file name. */
+fn file_safe(name: &str) -> String {
+    ascollapse(name).chars().map(|c| {
+        if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }
+    }).collect()
+}
+
+impl ChatLog {
+    /** Create a `ChatLog` that writes to files in `dir`, creating `dir` if
+    it doesn't exist. */
+    pub fn new(dir: &str) -> Result<ChatLog, String> {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            return Err(format!("Unable to create log directory {:?}: {}", dir, &e));
+        }
+        Ok(ChatLog {
+            dir: PathBuf::from(dir),
+            files: HashMap::new(),
+        })
+    }
+    
+    /** Return the path of the file lines logged under `kind` and `name` on
+    `date` go to. */
+    pub fn path(&self, kind: &str, name: &str, date: NaiveDate) -> PathBuf {
+        let mut p = self.dir.clone();
+        p.push(format!("{}-{}-{}.log", kind, file_safe(name), date.format("%Y-%m-%d")));
+        return p;
+    }
+    
+    /** Write `text` (shown at time `t`) to the log for the `kind` of
+    conversation ("room" or "priv") with `name`.
+    */
+    pub fn write(&mut self, kind: &str, name: &str, t: DateTime<Local>, text: &str)
+    -> Result<(), String> {
+        let key = format!("{}-{}", kind, file_safe(name));
+        let date = t.date().naive_local();
+        
+        let stale = match self.files.get(&key) {
+            Some((d, _)) => *d != date,
+            None => true,
+        };
+        if stale {
+            let path = self.path(kind, name, date);
+            let f = match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(f) => f,
+                Err(e) => {
+                    return Err(format!("Unable to open log file {}: {}", path.display(), &e));
+                },
+            };
+            self.files.insert(key.clone(), (date, f));
+        }
+        
+        let (_, f) = self.files.get_mut(&key).unwrap();
+        if let Err(e) = writeln!(f, "{} {}", t.format("%H:%M:%S"), text) {
+            return Err(format!("Unable to write to log file: {}", &e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+    
+    #[test]
+    fn rotation() {
+        let mut dir = std::env::temp_dir();
+        dir.push(format!("grel-chatlog-test-{}", std::process::id()));
+        let mut log = ChatLog::new(dir.to_str().unwrap()).unwrap();
+        
+        let day1 = Local.ymd(2021, 2, 14).and_hms(23, 59, 0);
+        let day2 = Local.ymd(2021, 2, 15).and_hms(0, 1, 0);
+        log.write("room", "Tracks of the World", day1, "José: one").unwrap();
+        log.write("room", "tracks of the world", day1, "José: two").unwrap();
+        log.write("room", "Tracks of the World", day2, "José: three").unwrap();
+        log.write("priv", "a/b", day2, "a/b: psst").unwrap();
+        
+        let read = |kind, name, t: DateTime<Local>| {
+            std::fs::read_to_string(log.path(kind, name, t.date().naive_local())).unwrap()
+        };
+        assert_eq!(read("room", "Tracks of the World", day1),
+                   "23:59:00 José: one\n23:59:00 José: two\n");
+        assert_eq!(read("room", "Tracks of the World", day2), "00:01:00 José: three\n");
+        assert!(log.path("priv", "a/b", day2.date().naive_local())
+                   .ends_with("priv-a_b-2021-02-15.log"));
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,233 @@
+/*!
+Tab completion for the client's input line.
+
+The `Completer` remembers the names it's seen (the current `Room`'s roster,
+plus the names of recent speakers and `Room`s) and knows the client's
+commands. Given the input line and the cursor position, it completes the
+word before the cursor against whichever of those make sense there:
+
+  * the first word of a command line, against the commands
+  * the second word of a command with subcommands (`;op`, `;admin`),
+    against those
+  * the arguments of commands that take `Room` names, against `Room` names
+  * anything else, against user names (the roster first)
+
+Matching goes through `ascollapse()`, so "jose" completes "José", and
+"jimb" completes "Jim Bob". Hitting Tab again right away replaces the
+completion with the next candidate, and so on around.
+*/
+use crate::user::ascollapse;
+
+/// number of recently-seen user and `Room` names remembered
+const RECENT: usize = 64;
+
+struct Cycle {
+    before: Vec<char>,
+    after: Vec<char>,
+    candidates: Vec<String>,
+    n: usize,
+    /// whether spaces should be taken out of the candidates inserted
+    squash: bool,
+    /// what should be inserted after the candidates
+    suffix: &'static str,
+    /// the input line, as last completed
+    line: Vec<char>,
+}
+
+impl Cycle {
+    /* Return the input line and cursor position with the current
+    candidate in place. */
+    fn fill(&mut self) -> (Vec<char>, usize) {
+        let mut line = self.before.clone();
+        let word = &self.candidates[self.n];
+        if self.squash {
+            line.extend(word.chars().filter(|c| !c.is_whitespace()));
+        } else {
+            line.extend(word.chars());
+        }
+        line.extend(self.suffix.chars());
+        let ip = line.len();
+        line.extend(self.after.iter());
+        self.line = line.clone();
+        return (line, ip);
+    }
+}
+
+pub struct Completer {
+    cmd_char: char,
+    commands: Vec<String>,
+    subcommands: Vec<(String, Vec<String>)>,
+    room_commands: Vec<String>,
+    roster: Vec<String>,
+    /// most recent first
+    names: Vec<String>,
+    /// most recent first
+    rooms: Vec<String>,
+    cycle: Option<Cycle>,
+}
+
+/* Move `item` to the front of `v`, adding it if it isn't there already and
+dropping the oldest item if there are too many. */
+fn remember(v: &mut Vec<String>, item: &str) {
+    if let Some(n) = v.iter().position(|x| x == item) { v.remove(n); }
+    v.insert(0, item.to_string());
+    v.truncate(RECENT);
+}
+
+impl Completer {
+    /** Create a `Completer` for command lines beginning with `cmd_char`,
+    with the given `commands`.
+    */
+    pub fn new(cmd_char: char, commands: &[&str]) -> Completer {
+        Completer {
+            cmd_char,
+            commands: commands.iter().map(|s| s.to_string()).collect(),
+            subcommands: Vec::new(),
+            room_commands: Vec::new(),
+            roster: Vec::new(),
+            names: Vec::new(),
+            rooms: Vec::new(),
+            cycle: None,
+        }
+    }
+    
+    /** Set the subcommands of the command `cmd`. */
+    pub fn set_subcommands(&mut self, cmd: &str, subs: &[&str]) {
+        let subs: Vec<String> = subs.iter().map(|s| s.to_string()).collect();
+        self.subcommands.push((cmd.to_string(), subs));
+    }
+    
+    /** Set which commands take `Room` names as arguments. */
+    pub fn set_room_commands(&mut self, cmds: &[&str]) {
+        self.room_commands = cmds.iter().map(|s| s.to_string()).collect();
+    }
+    
+    /** Replace the roster of the current `Room`. */
+    pub fn set_roster(&mut self, names: Vec<String>) { self.roster = names; }
+    
+    /** Note a user name seen recently. */
+    pub fn saw_name(&mut self, name: &str) { remember(&mut self.names, name); }
+    
+    /** Note a `Room` name seen recently. */
+    pub fn saw_room(&mut self, name: &str) { remember(&mut self.rooms, name); }
+    
+    /* Return the candidates for completing a word in the given context,
+    whether their spaces should be taken out, and what should follow them.
+    */
+    fn candidates(&self, words_before: &[String], in_cmd: bool)
+    -> (Vec<String>, bool, &'static str) {
+        if !in_cmd {
+            let mut v = self.roster.clone();
+            v.extend(self.names.iter().cloned());
+            return (v, false, "");
+        }
+        
+        let cmd = match words_before.first() {
+            None => { return (self.commands.clone(), false, " "); },
+            Some(c) => c.to_lowercase(),
+        };
+        if words_before.len() == 1 {
+            if let Some((_, subs)) = self.subcommands.iter().find(|(c, _)| c == &cmd) {
+                return (subs.clone(), false, " ");
+            }
+        }
+        if self.room_commands.contains(&cmd) {
+            return (self.rooms.clone(), true, " ");
+        }
+        let mut v = self.roster.clone();
+        v.extend(self.names.iter().cloned());
+        return (v, true, " ");
+    }
+    
+    /** Complete the word before position `ip` in the input `line`.
+    
+    Returns the new input line and cursor position, or `None` if there's
+    nothing to complete it with.
+    */
+    pub fn complete(&mut self, line: &[char], ip: usize) -> Option<(Vec<char>, usize)> {
+        /* If the line hasn't changed since the last completion, move on to
+        the next candidate. */
+        if let Some(cyc) = self.cycle.as_mut() {
+            if cyc.line == line {
+                cyc.n = (cyc.n + 1) % cyc.candidates.len();
+                return Some(cyc.fill());
+            }
+        }
+        self.cycle = None;
+        
+        let ip = ip.min(line.len());
+        let in_cmd = line.first() == Some(&self.cmd_char);
+        let body_start = if in_cmd { 1 } else { 0 };
+        let mut start = ip;
+        while start > body_start && !line[start - 1].is_whitespace() { start -= 1; }
+        
+        let word: String = line[start..ip].iter().collect();
+        let before: String = line[body_start..start].iter().collect();
+        let words_before: Vec<String> = before.split_whitespace()
+            .map(|s| s.to_string()).collect();
+        
+        let (all, squash, suffix) = self.candidates(&words_before, in_cmd);
+        let target = ascollapse(&word);
+        let mut seen: Vec<String> = Vec::new();
+        let mut candidates: Vec<String> = Vec::new();
+        for c in all.into_iter() {
+            let cc = ascollapse(&c);
+            if cc.starts_with(&target) && !seen.contains(&cc) {
+                seen.push(cc);
+                candidates.push(c);
+            }
+        }
+        if candidates.len() == 0 { return None; }
+        
+        let mut cyc = Cycle {
+            before: line[..start].to_vec(),
+            after: line[ip..].to_vec(),
+            candidates,
+            n: 0,
+            squash,
+            suffix,
+            line: Vec::new(),
+        };
+        let res = cyc.fill();
+        self.cycle = Some(cyc);
+        return Some(res);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    fn chars(s: &str) -> Vec<char> { s.chars().collect() }
+    
+    fn complete(c: &mut Completer, s: &str) -> Option<String> {
+        let line = chars(s);
+        c.complete(&line, line.len()).map(|(v, _)| v.into_iter().collect())
+    }
+    
+    #[test]
+    fn completion() {
+        let mut c = Completer::new(';', &["priv", "op", "join", "quit"]);
+        c.set_subcommands("op", &["kick", "key"]);
+        c.set_room_commands(&["join"]);
+        c.set_roster(vec!["José".to_string(), "Jim Bob".to_string()]);
+        c.saw_name("Jimmy");
+        c.saw_room("Tracks of the World");
+        
+        assert_eq!(complete(&mut c, "hi jose").as_deref(), Some("hi José"));
+        assert_eq!(complete(&mut c, "hey jim").as_deref(), Some("hey Jim Bob"));
+        assert_eq!(complete(&mut c, "hey Jim Bob").as_deref(), Some("hey Jimmy"));
+        assert_eq!(complete(&mut c, "hey Jimmy").as_deref(), Some("hey Jim Bob"));
+        assert_eq!(complete(&mut c, "hey xyz"), None);
+        
+        assert_eq!(complete(&mut c, ";pr").as_deref(), Some(";priv "));
+        assert_eq!(complete(&mut c, ";priv jimb").as_deref(), Some(";priv JimBob "));
+        assert_eq!(complete(&mut c, ";op ki").as_deref(), Some(";op kick "));
+        assert_eq!(complete(&mut c, ";join tra").as_deref(), Some(";join TracksoftheWorld "));
+        
+        let line = chars("jos and me");
+        let (v, ip) = c.complete(&line, 3).unwrap();
+        assert_eq!(v.into_iter().collect::<String>(), "José and me");
+        assert_eq!(ip, 4);
+    }
+}
@@ -14,6 +14,8 @@ use std::fmt::Write;
 
 use simplelog::LevelFilter;
 
+use crate::theme::Theme;
+
 const CLIENT_NAME: &str = "grel.toml";
 const SERVER_NAME: &str = "greld.toml";
 
@@ -23,9 +25,18 @@ const SERVER_NAME: &str = "greld.toml";
 */
 const ADDR:             &str = "127.0.0.1:51516";   // server address (and address client tries)
 const SERVER_LOG:       &str = "greld.log";         // server log file
+const PIDFILE:          &str = "d.pid";             // server pidfile
+const AUDIT_LOG:        &str = "greld-audit.log";   // server admin action log file
+const STATE_FILE:       &str = "greld-state.json";  // server saved state
 const NAME:             &str = "grel user";         // client user name
 const LOBBY_NAME:       &str = "Lobby";             // server landing room name
 const WELCOME:          &str = "Welcome to a grel server."; // server welcome message
+const SHUTDOWN:         &str = "The server is shutting down."; // server goodbye message
+const SHUTDOWN_FLUSH:    u64 = 2000;                // server, max time spent sending goodbyes
+const SAVE_INTERVAL:     u64 = 300;                 // server, seconds between state saves
+const MEMO_EXPIRY:       u64 = 30;                  // server, days undelivered memos are kept
+const MEMO_QUOTA:      usize = 20;                  // server, max undelivered memos per recipient
+const MAX_MATCHES:     usize = 50;                  // server, max results of a who or rooms query
 const SERVER_TICK:       u64 = 500;                 // server, min time through main loop
 const BYTE_LIMIT:      usize = 512;                 // server user rate limiting byte quota
 const BYTE_TICK:       usize = 6;                   // server byte quota dissipation per tick
@@ -39,6 +50,9 @@ const ROSTER_WIDTH:      u16 = 24;    // Also server max user name and max room
 const CMD_CHAR:         char = ';';
 const MIN_SCROLLBACK:  usize = 1000;                // client `Line`s of scrollback kept
 const MAX_SCROLLBACK:  usize = 2000;                // client will trim scrollback to MIN_SCROLLBACK when this many `Line`s reached
+const HISTORY_SIZE:    usize = 500;                 // client input lines remembered
+const NOTIFY:           &str = "bell";              // client, how to call attention to mentions
+const TIMESTAMP:        &str = "%H:%M";             // client scrollback timestamp format
 
 /** Generate a platform-appropriate path for configuration files. */
 fn default_config_dir() -> PathBuf {
@@ -62,6 +76,85 @@ fn read_first_to_string(ps: &[PathBuf]) -> Result<String, String> {
     Err(misses)
 }
 
+/** Load the theme `name`: one of the built-in themes, or else the theme
+file at the path `name`, or else `name.toml` in the `themes` subdirectory
+of the default configuration directory.
+*/
+fn load_theme(name: &str) -> Result<Theme, String> {
+    if let Some(t) = Theme::builtin(name) { return Ok(t); }
+    
+    let mut in_config = default_config_dir();
+    in_config.push("themes");
+    in_config.push(format!("{}.toml", name));
+    let src = read_first_to_string(&[PathBuf::from(name), in_config])
+        .map_err(|e| format!("Unable to load theme {:?}: {}", name, &e))?;
+    Theme::from_toml(&src).map_err(|e| format!("Error in theme {:?}: {}", name, &e))
+}
+
+/** A server administrator account, from an `[[admins]]` stanza in the
+server config file. `password` may be either plain text or a hash as
+produced by `passwd::hash()` (recognizable by its leading `$argon2`).
+*/
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq)]
+pub struct AdminAccount {
+    pub name: String,
+    password: String,
+}
+
+impl AdminAccount {
+    /** Return whether `password` is this account's password. */
+    pub fn check(&self, password: &str) -> bool {
+        if self.password.starts_with("$argon2") {
+            super::passwd::verify(&self.password, password)
+        } else {
+            self.password == password
+        }
+    }
+}
+
+/* Keep passwords out of the "Configuration: ..." line greld prints. */
+impl std::fmt::Debug for AdminAccount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "AdminAccount {{ name: {:?}, .. }}", &self.name)
+    }
+}
+
+/** The places a server config file is looked for, in order: just `path`,
+if one is supplied, otherwise the usual places.
+*/
+fn server_config_paths(path: Option<&str>) -> Vec<PathBuf> {
+    if let Some(p) = path { return vec![PathBuf::from(p)]; }
+    let mut pathz: Vec<PathBuf> = Vec::new();
+    pathz.push(PathBuf::from(SERVER_NAME));
+    let mut p = default_config_dir();
+    p.push(SERVER_NAME);
+    pathz.push(p);
+    pathz
+}
+
+/* Numeric log levels, as used in the server config file. */
+fn level_filter(n: u8) -> LevelFilter {
+    match n {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/** Interpret a log level given either as a number from 0 (off) to 5
+(trace), like in the server config file, or by name ("warn", "debug", &c.).
+*/
+pub fn parse_log_level(s: &str) -> Result<LevelFilter, String> {
+    if let Ok(n) = s.parse::<u8>() {
+        if n > 5 { return Err(format!("Log level {} is higher than 5.", n)); }
+        return Ok(level_filter(n));
+    }
+    s.parse::<LevelFilter>().map_err(|_| format!("Unrecognized log level {:?}.", s))
+}
+
 /** The `ServerConfigFile` deserializes from a `.toml` file to a struct
 of Rust primitives. Its values are then translated into less primitive
 types (or at least some of them are) and shoved into a `ServerConfig`
@@ -77,10 +170,20 @@ struct ServerConfigFile {
     max_room_name_length: Option<usize>,
     lobby_name:           Option<String>,
     welcome:              Option<String>,
+    shutdown_message:     Option<String>,
+    shutdown_flush_ms:    Option<u64>,
     log_file:             Option<String>,
     log_level:            Option<u8>,
     byte_limit:           Option<usize>,
     bytes_per_tick:       Option<usize>,
+    audit_log:            Option<String>,
+    pidfile:              Option<String>,
+    state_file:           Option<String>,
+    save_interval_s:      Option<u64>,
+    memo_expiry_days:     Option<u64>,
+    memo_quota:           Option<usize>,
+    max_matches:          Option<usize>,
+    admins:               Option<Vec<AdminAccount>>,
 }
 
 /** `ServerConfigFile` implements `Default` because this is what is used if
@@ -97,10 +200,20 @@ impl std::default::Default for ServerConfigFile {
             max_room_name_length: None, //24,
             lobby_name:           None, //String::from(LOBBY_NAME),
             welcome:              None, //String::from(WELCOME),
+            shutdown_message:     None, //String::from(SHUTDOWN),
+            shutdown_flush_ms:    None, //SHUTDOWN_FLUSH,
             log_file:             None, //String::from(SERVER_LOG),
             log_level:            None, //5,
             byte_limit:           None, //BYTE_LIMIT,
             bytes_per_tick:       None, //BYTE_TICK,
+            audit_log:            None, //String::from(AUDIT_LOG),
+            pidfile:              None, //String::from(PIDFILE),
+            state_file:           None, //String::from(STATE_FILE),
+            save_interval_s:      None, //SAVE_INTERVAL,
+            memo_expiry_days:     None, //MEMO_EXPIRY,
+            memo_quota:           None, //MEMO_QUOTA,
+            max_matches:          None, //MAX_MATCHES,
+            admins:               None,
         }
     }
 }
@@ -118,59 +231,151 @@ pub struct ServerConfig {
     pub max_room_name_length: usize,
     pub lobby_name: String,
     pub welcome: String,
+    pub shutdown_message: String,
+    pub shutdown_flush: Duration,
     pub log_file: String,
     pub log_level: LevelFilter,
     pub byte_limit: usize,
     pub byte_tick: usize,
+    pub audit_log: String,
+    pub pidfile: String,
+    pub state_file: String,
+    pub save_interval: Duration,
+    pub memo_expiry: Duration,
+    pub memo_quota: usize,
+    pub max_matches: usize,
+    pub admins: Vec<AdminAccount>,
 }
 
 impl ServerConfig {
-    /** This single method attempts to read a configuration file first
-    from `./greld.toml` and then from
-    `your_system's_standard_config_directory/greld.toml`
-    and return a `ServerConfig` object.
+    /** Generate a configuration for the server.
+    
+    If a `path` is supplied, the configuration is read from that file.
+    Otherwise, will attempt to read from `greld.toml` in the current
+    directory, and then in the directory returned by `default_config_dir()`;
+    if neither of those can be read, the default configuration (which
+    isn't super useful because it binds to 127.0.0.1) is used.
     
-    Errors _parsing_ a config file will terminate with a message. Errors
-    _reading_ a config file (probably "file not found") will result in
-    using the default configuration (which isn't super useful because it
-    binds to 127.0.0.1).
+    Errors _parsing_ (or validating) a config file are returned, as is
+    failure to read a specifically-supplied `path`.
     */
-    pub fn configure() -> ServerConfig {
-        let mut pathz: Vec<PathBuf> = Vec::new();
-        pathz.push(PathBuf::from(SERVER_NAME));
-        {
-            let mut p = default_config_dir();
-            p.push(SERVER_NAME);
-            pathz.push(p);
-        }
-        
-        let cfgf: ServerConfigFile = match read_first_to_string(&pathz) {
+    pub fn configure(path: Option<&str>) -> Result<ServerConfig, String> {
+        let cfgf: ServerConfigFile = match read_first_to_string(&server_config_paths(path)) {
             Ok(s) => match toml::from_str(&s) {
                 Ok(x) => x,
-                Err(e) => {
-                    println!("Error parsing config file: {}", &e);
-                    std::process::exit(1);
-                },
+                Err(e) => { return Err(format!("Error parsing config file: {}", &e)); },
             },
             Err(e) => {
+                if path.is_some() { return Err(e); }
                 println!("Error reading config file: {}", &e);
                 println!("Using default configuration.");
                 ServerConfigFile::default()
             },
         };
         
+        let cfg = ServerConfig::from_file(cfgf);
+        cfg.validate()?;
+        Ok(cfg)
+    }
+    
+    /** Read the configuration file again (for reloading a running server).
+    
+    Unlike `.configure()`, failing to read the file is an error, rather
+    than something to fall back on defaults over.
+    */
+    pub fn reread(path: Option<&str>) -> Result<ServerConfig, String> {
+        let s = read_first_to_string(&server_config_paths(path))?;
+        let cfg = match toml::from_str(&s) {
+            Ok(cfgf) => ServerConfig::from_file(cfgf),
+            Err(e) => { return Err(format!("Error parsing config file: {}", &e)); },
+        };
+        cfg.validate()?;
+        Ok(cfg)
+    }
+    
+    /** Generate a default config file in the default location, and return
+    that location as a string (so it can be displayed to the user).
+    */
+    pub fn generate() -> Result<String, String> {
+        let cfg = ServerConfigFile {
+            address:              Some(String::from(ADDR)),
+            tick_ms:              Some(SERVER_TICK),
+            blackout_to_ping_ms:  Some(BLACKOUT_TO_PING),
+            blackout_to_kick_ms:  Some(BLACKOUT_TO_KICK),
+            max_user_name_length: Some(ROSTER_WIDTH as usize),
+            max_room_name_length: Some(ROSTER_WIDTH as usize),
+            lobby_name:           Some(String::from(LOBBY_NAME)),
+            welcome:              Some(String::from(WELCOME)),
+            shutdown_message:     Some(String::from(SHUTDOWN)),
+            shutdown_flush_ms:    Some(SHUTDOWN_FLUSH),
+            log_file:             Some(String::from(SERVER_LOG)),
+            log_level:            Some(2),
+            byte_limit:           Some(BYTE_LIMIT),
+            bytes_per_tick:       Some(BYTE_TICK),
+            audit_log:            Some(String::from(AUDIT_LOG)),
+            pidfile:              Some(String::from(PIDFILE)),
+            state_file:           Some(String::from(STATE_FILE)),
+            save_interval_s:      Some(SAVE_INTERVAL),
+            memo_expiry_days:     Some(MEMO_EXPIRY),
+            memo_quota:           Some(MEMO_QUOTA),
+            max_matches:          Some(MAX_MATCHES),
+            admins:               None,
+        };
+        
+        let mut cfg_path = default_config_dir();
+        cfg_path.push(SERVER_NAME);
+        let cfg_str = toml::to_string(&cfg).unwrap();
+        
+        match std::fs::write(&cfg_path, &cfg_str) {
+            Ok(()) => Ok(cfg_path.to_string_lossy().to_string()),
+            Err(e) => Err(format!("Error writing new config file {}: {}",
+                                  &cfg_path.display(), &e)),
+        }
+    }
+    
+    /** Check for values that would make the server misbehave. */
+    pub fn validate(&self) -> Result<(), String> {
+        use std::net::ToSocketAddrs;
+        if let Err(e) = self.address.to_socket_addrs() {
+            return Err(format!("Bad address {:?}: {}", &self.address, &e));
+        }
+        if self.min_tick.as_millis() == 0 {
+            return Err("tick_ms must be greater than 0".to_string());
+        }
+        if self.blackout_time_to_kick <= self.blackout_time_to_ping {
+            return Err("blackout_to_kick_ms must be greater than blackout_to_ping_ms".to_string());
+        }
+        if self.max_user_name_length == 0 || self.max_room_name_length == 0 {
+            return Err("maximum name lengths must be greater than 0".to_string());
+        }
+        if self.lobby_name.trim().len() == 0 {
+            return Err("lobby_name cannot be blank".to_string());
+        }
+        if self.save_interval.as_secs() == 0 {
+            return Err("save_interval_s must be greater than 0".to_string());
+        }
+        if self.max_matches == 0 {
+            return Err("max_matches must be greater than 0".to_string());
+        }
+        for (n, a) in self.admins.iter().enumerate() {
+            if a.name.len() == 0 || a.password.len() == 0 {
+                return Err("admin accounts need both a name and a password".to_string());
+            }
+            if self.admins[..n].iter().any(|b| b.name == a.name) {
+                return Err(format!("more than one admin account named {:?}", &a.name));
+            }
+        }
+        Ok(())
+    }
+    
+    fn from_file(cfgf: ServerConfigFile) -> ServerConfig {
         let logl: LevelFilter = match cfgf.log_level {
             None    => LOG_LEVEL,
-            Some(0) => LevelFilter::Off,
-            Some(1) => LevelFilter::Error,
-            Some(2) => LevelFilter::Warn,
-            Some(3) => LevelFilter::Info,
-            Some(4) => LevelFilter::Debug,
-            Some(5) => LevelFilter::Trace,
-            Some(_) => {
+            Some(n) if n > 5 => {
                 println!("Log levels higher than 5 not supported; setting to 5.");
                 LevelFilter::Trace
             },
+            Some(n) => level_filter(n),
         };
         
         ServerConfig {
@@ -186,12 +391,83 @@ impl ServerConfig {
                                     .unwrap_or(ROSTER_WIDTH as usize),
             lobby_name: cfgf.lobby_name.unwrap_or(LOBBY_NAME.to_string()),
             welcome:    cfgf.welcome   .unwrap_or(WELCOME.to_string()),
+            shutdown_message: cfgf.shutdown_message.unwrap_or(SHUTDOWN.to_string()),
+            shutdown_flush: Duration::from_millis(cfgf.shutdown_flush_ms
+                                    .unwrap_or(SHUTDOWN_FLUSH)),
             log_file:   cfgf.log_file  .unwrap_or(SERVER_LOG.to_string()),
             log_level:  logl,
             byte_limit: cfgf.byte_limit.unwrap_or(BYTE_LIMIT),
             byte_tick:  cfgf.bytes_per_tick.unwrap_or(BYTE_TICK),
+            audit_log:  cfgf.audit_log .unwrap_or(AUDIT_LOG.to_string()),
+            pidfile:    cfgf.pidfile   .unwrap_or(PIDFILE.to_string()),
+            state_file: cfgf.state_file.unwrap_or(STATE_FILE.to_string()),
+            save_interval: Duration::from_secs(cfgf.save_interval_s
+                                    .unwrap_or(SAVE_INTERVAL)),
+            memo_expiry: Duration::from_secs(86400 * cfgf.memo_expiry_days
+                                    .unwrap_or(MEMO_EXPIRY)),
+            memo_quota: cfgf.memo_quota.unwrap_or(MEMO_QUOTA),
+            max_matches: cfgf.max_matches.unwrap_or(MAX_MATCHES),
+            admins:     cfgf.admins    .unwrap_or_default(),
         }
     }
+    
+    /** Take on the values of the freshly-read `new` configuration, except
+    those that can't change while the server is running.
+    
+    Returns the names of the settings that changed, and the names of the
+    settings that changed in `new` but that require a restart to take
+    effect (and so weren't changed).
+    */
+    pub fn apply(&mut self, new: ServerConfig) -> (Vec<&'static str>, Vec<&'static str>) {
+        let mut changed: Vec<&'static str> = Vec::new();
+        let mut restart: Vec<&'static str> = Vec::new();
+        
+        macro_rules! live {
+            ($field:ident, $name:expr) => {
+                if self.$field != new.$field {
+                    self.$field = new.$field;
+                    changed.push($name);
+                }
+            };
+        }
+        macro_rules! fixed {
+            ($field:ident, $name:expr) => {
+                if self.$field != new.$field { restart.push($name); }
+            };
+        }
+        
+        fixed!(address,               "address");
+        live!(min_tick,               "tick_ms");
+        live!(blackout_time_to_ping,  "blackout_to_ping_ms");
+        live!(blackout_time_to_kick,  "blackout_to_kick_ms");
+        live!(max_user_name_length,   "max_user_name_length");
+        live!(max_room_name_length,   "max_room_name_length");
+        fixed!(lobby_name,            "lobby_name");
+        live!(welcome,                "welcome");
+        live!(shutdown_message,       "shutdown_message");
+        live!(shutdown_flush,         "shutdown_flush_ms");
+        fixed!(log_file,              "log_file");
+        live!(log_level,              "log_level");
+        live!(byte_limit,             "byte_limit");
+        live!(byte_tick,              "bytes_per_tick");
+        live!(audit_log,              "audit_log");
+        fixed!(pidfile,               "pidfile");
+        live!(state_file,             "state_file");
+        live!(save_interval,          "save_interval_s");
+        live!(memo_expiry,            "memo_expiry_days");
+        live!(memo_quota,             "memo_quota");
+        live!(max_matches,            "max_matches");
+        live!(admins,                 "admins");
+        
+        (changed, restart)
+    }
+    
+    /** Return the admin account with the given name and password, if
+    there is one.
+    */
+    pub fn admin(&self, name: &str, password: &str) -> Option<&AdminAccount> {
+        self.admins.iter().find(|a| a.name == name && a.check(password))
+    }
 }
 
 /** Represents the `[colors]` stanza in the client config file.
@@ -199,23 +475,37 @@ impl ServerConfig {
     By default these are all `None`, using the default color scheme
     in the `screen` module.
 */
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[derive(serde::Serialize, serde::Deserialize, Debug, Default)]
 pub struct Colors {
     pub dim_foreground: Option<u8>,
     pub dim_background: Option<u8>,
     pub highlight_foreground: Option<u8>,
     pub highlight_background: Option<u8>,
     pub underline_as_bold: Option<bool>,
+    /// palette speakers' names are colored from (empty for no coloring)
+    pub nick_colors: Option<Vec<u8>>,
+    /// whether to stick to the 16 basic colors when coloring names
+    pub sixteen_colors: Option<bool>,
 }
 
-impl std::default::Default for Colors {
-    fn default() -> Self {
-        Self {
-            dim_foreground: None,
-            dim_background: None,
-            highlight_foreground: None,
-            highlight_background: None,
-            underline_as_bold: None,
+/** How the client calls attention to lines that mention the user. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Notify {
+    /// not at all (beyond highlighting them)
+    None,
+    /// by ringing the terminal bell
+    Bell,
+    /// with an OSC 9 desktop notification escape sequence
+    Osc,
+}
+
+impl Notify {
+    fn parse(s: &str) -> Result<Notify, String> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Notify::None),
+            "bell" => Ok(Notify::Bell),
+            "osc"  => Ok(Notify::Osc),
+            _ => Err(format!("notify must be one of \"none\", \"bell\", or \"osc\", not {:?}", s)),
         }
     }
 }
@@ -236,6 +526,13 @@ struct ClientConfigFile {
     cmd_char:       Option<char>,
     max_scrollback: Option<usize>,
     min_scrollback: Option<usize>,
+    history_size:   Option<usize>,
+    history_file:   Option<String>,
+    highlights:     Option<Vec<String>>,
+    notify:         Option<String>,
+    timestamp:      Option<String>,
+    log_dir:        Option<String>,
+    theme:          Option<String>,
     colors: Option<Colors>,
 }
 
@@ -254,6 +551,13 @@ impl std::default::Default for ClientConfigFile {
             cmd_char:       None,
             max_scrollback: None,
             min_scrollback: None,
+            history_size:   None,
+            history_file:   None,
+            highlights:     None,
+            notify:         None,
+            timestamp:      None,
+            log_dir:        None,
+            theme:          None,
             colors:         None,
         }
     }
@@ -273,6 +577,17 @@ pub struct ClientConfig {
     pub cmd_char:       char,
     pub max_scrollback: usize,
     pub min_scrollback: usize,
+    pub history_size:   usize,
+    pub history_file:   Option<String>,
+    /// words (besides the user's name) that make a line a mention
+    pub highlights:     Vec<String>,
+    pub notify:         Notify,
+    /// `strftime`-style format for scrollback timestamps; `None` for none
+    pub timestamp:      Option<String>,
+    /// where to write chat logs, if anywhere
+    pub log_dir:        Option<String>,
+    /// applied on top of the `colors`
+    pub theme:          Option<Theme>,
     pub colors:         Option<Colors>,
 }
 
@@ -312,6 +627,18 @@ impl ClientConfig {
         let max_scroll = f.max_scrollback.unwrap_or(MAX_SCROLLBACK);
         let min_scroll = f.min_scrollback.unwrap_or(MIN_SCROLLBACK);
         let cmd_char   = f.cmd_char.unwrap_or(CMD_CHAR);
+        let notify     = Notify::parse(f.notify.as_deref().unwrap_or(NOTIFY))?;
+        let timestamp  = match f.timestamp {
+            None => Some(String::from(TIMESTAMP)),
+            Some(s) if s.is_empty() => None,
+            Some(s) => {
+                use chrono::format::{Item, StrftimeItems};
+                if StrftimeItems::new(&s).any(|i| i == Item::Error) {
+                    return Err(format!("timestamp {:?} is not a valid time format", &s));
+                }
+                Some(s)
+            },
+        };
         
         if max_scroll < min_scroll {
             return Err("max_scrollback cannot be smaller than min_scrollback".to_string());
@@ -319,6 +646,17 @@ impl ClientConfig {
         if (cmd_char as u32) > 128 {
             return Err("cmd_char must be an ASCII character".to_string());
         };
+        let theme = match &f.theme {
+            None => None,
+            Some(name) => Some(load_theme(name)?),
+        };
+        if let Some(cols) = &f.colors {
+            if let (Some(true), Some(nicks)) = (cols.sixteen_colors, &cols.nick_colors) {
+                if nicks.iter().any(|n| *n > 15) {
+                    return Err("with sixteen_colors, nick_colors must all be from 0 to 15".to_string());
+                }
+            }
+        }
         
         let cc = ClientConfig {
             address:      f.address .unwrap_or(String::from(ADDR)),
@@ -327,9 +665,16 @@ impl ClientConfig {
             block: Duration::from_millis(f.block_ms.unwrap_or(BLOCK_TIMEOUT)),
             read_size:    f.read_size.unwrap_or(READ_SIZE),
             roster_width: f.roster_width.unwrap_or(ROSTER_WIDTH),
-            cmd_char:       cmd_char,
+            cmd_char,
             max_scrollback: max_scroll,
             min_scrollback: min_scroll,
+            history_size: f.history_size.unwrap_or(HISTORY_SIZE),
+            history_file: f.history_file,
+            highlights:   f.highlights.unwrap_or_default(),
+            notify,
+            timestamp,
+            log_dir:      f.log_dir,
+            theme,
             colors:       f.colors,
         };
         
@@ -350,6 +695,13 @@ impl ClientConfig {
             cmd_char:       Some(CMD_CHAR),
             max_scrollback: Some(MAX_SCROLLBACK),
             min_scrollback: Some(MIN_SCROLLBACK),
+            history_size:   Some(HISTORY_SIZE),
+            history_file:   None,
+            highlights:     Some(Vec::new()),
+            notify:         Some(String::from(NOTIFY)),
+            timestamp:      Some(String::from(TIMESTAMP)),
+            log_dir:        None,
+            theme:          None,
             colors:         Some(Colors::default()),
         };
         
@@ -0,0 +1,157 @@
+/*!
+The client's input line history.
+
+`History` holds the most recent lines the user has submitted (up to some
+limit), oldest first, and keeps track of where the user is while stepping
+back and forth through them. It can also be saved to and loaded from a
+plain text file, one line per line.
+*/
+
+pub struct History {
+    lines: Vec<String>,
+    limit: usize,
+    /// index of the line being shown while browsing, if browsing
+    pos: Option<usize>,
+    /// what was on the input line when browsing started
+    draft: String,
+}
+
+impl History {
+    /** Create an empty `History` that will hold at most `limit` lines. */
+    pub fn new(limit: usize) -> History {
+        History {
+            lines: Vec::new(),
+            limit,
+            pos: None,
+            draft: String::new(),
+        }
+    }
+    
+    /** Read a `History` from the file at `path`. A missing file is just an
+    empty history; if the file holds more than `limit` lines, only the last
+    `limit` are kept.
+    */
+    pub fn load(path: &str, limit: usize) -> Result<History, String> {
+        let mut h = History::new(limit);
+        let s = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => { return Ok(h); },
+            Err(e) => { return Err(format!("Unable to read history file {:?}: {}", path, &e)); },
+        };
+        for line in s.lines() { h.push(line); }
+        return Ok(h);
+    }
+    
+    /** Write the `History` to the file at `path`. */
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut s = String::new();
+        for line in self.lines.iter() {
+            s.push_str(line);
+            s.push('\n');
+        }
+        if let Err(e) = std::fs::write(path, s.as_bytes()) {
+            return Err(format!("Unable to write history file {:?}: {}", path, &e));
+        }
+        Ok(())
+    }
+    
+    pub fn len(&self) -> usize { self.lines.len() }
+    
+    pub fn is_empty(&self) -> bool { self.lines.is_empty() }
+    
+    /** Return the line at index `n` (0 is the oldest). */
+    pub fn get(&self, n: usize) -> Option<&str> {
+        self.lines.get(n).map(|s| s.as_str())
+    }
+    
+    /** Add a submitted line to the end of the `History`, and stop browsing.
+    Blank lines and lines identical to the previous one aren't recorded.
+    */
+    pub fn push(&mut self, line: &str) {
+        self.reset();
+        if line.trim().is_empty() { return; }
+        if self.lines.last().map(|s| s.as_str()) == Some(line) { return; }
+        
+        self.lines.push(line.to_string());
+        if self.lines.len() > self.limit {
+            let excess = self.lines.len() - self.limit;
+            self.lines.drain(..excess);
+        }
+    }
+    
+    /** Stop browsing, forgetting where we were. */
+    pub fn reset(&mut self) {
+        self.pos = None;
+        self.draft.clear();
+    }
+    
+    /** Step back to the next older line. `current` is what's on the
+    input line now; if we weren't already browsing, it's remembered so that
+    stepping forward past the newest line can restore it.
+    
+    Returns the line to show, or `None` if there's nothing older.
+    */
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        let n = match self.pos {
+            Some(0) => { return None; },
+            Some(n) => n - 1,
+            None => {
+                if self.lines.is_empty() { return None; }
+                self.draft = current.to_string();
+                self.lines.len() - 1
+            },
+        };
+        self.pos = Some(n);
+        return Some(&self.lines[n]);
+    }
+    
+    /** Step forward to the next newer line, or back to the line that was
+    being composed when browsing started.
+    
+    Returns the line to show, or `None` if we weren't browsing.
+    */
+    pub fn newer(&mut self) -> Option<&str> {
+        let n = self.pos? + 1;
+        if n < self.lines.len() {
+            self.pos = Some(n);
+            return Some(&self.lines[n]);
+        }
+        self.pos = None;
+        return Some(&self.draft);
+    }
+    
+    /** Return the index of the newest line older than index `before` that
+    contains `pat`. (Pass `self.len()` to search the whole `History`.)
+    */
+    pub fn search(&self, pat: &str, before: usize) -> Option<usize> {
+        let end = before.min(self.lines.len());
+        self.lines[..end].iter().rposition(|s| s.contains(pat))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    #[test]
+    fn browse_and_search() {
+        let mut h = History::new(3);
+        for s in ["one", "two", "", "two", "three", "four"].iter() { h.push(s); }
+        assert_eq!(h.len(), 3);
+        assert_eq!(h.get(0), Some("two"));
+        
+        assert_eq!(h.older("draft"), Some("four"));
+        assert_eq!(h.older(""), Some("three"));
+        assert_eq!(h.older(""), Some("two"));
+        assert_eq!(h.older(""), None);
+        assert_eq!(h.newer(), Some("three"));
+        assert_eq!(h.newer(), Some("four"));
+        assert_eq!(h.newer(), Some("draft"));
+        assert_eq!(h.newer(), None);
+        
+        assert_eq!(h.search("o", h.len()), Some(2));
+        assert_eq!(h.search("o", 2), Some(0));
+        assert_eq!(h.search("o", 0), None);
+        assert_eq!(h.search("xyz", h.len()), None);
+    }
+}
@@ -1,11 +1,21 @@
+/* Explicit `return`s and `.len() == 0` are house style. */
+#![allow(clippy::needless_return, clippy::len_zero)]
+
 pub mod sock;
 pub mod user;
 pub mod room;
 pub mod unidata;
 pub mod config;
+pub mod passwd;
+pub mod state;
+pub mod matching;
 //pub mod proto2;
 pub mod line;
 pub mod screen;
+pub mod history;
+pub mod complete;
+pub mod chatlog;
+pub mod theme;
 
 // new!
 pub mod proto3;
@@ -50,6 +50,20 @@ impl Style {
         
         return Style(String::from_utf8(buff).unwrap());
     }
+    
+    /** Return a `Style` with the basic ANSI foreground color `n` (0-7 for
+    the normal colors, 8-15 for the bright ones), written with the escape
+    codes understood by 16-color terminals. (`crossterm` writes even these
+    colors with 256-color codes.)
+    */
+    pub fn basic(n: u8) -> Style {
+        let code = match n {
+            0..=7  => 30 + n,
+            8..=15 => 90 + (n - 8),
+            _      => 39,
+        };
+        return Style(format!("\x1b[{}m", code));
+    }
 }
 
 impl std::ops::Deref for Style {
@@ -75,7 +89,7 @@ formatting information. It can be built up by pushing chunks of text onto the
 end of it. You can request a rendering of it at different wrapping widths.
 
 ```
-use grel::ctline::{Style, Line};
+use grel::line::{Style, Line};
 use crossterm::style;
 
 let blues = Style::new(Some(style::Color::Cyan),
@@ -108,8 +122,11 @@ for line in x.lines(60).iter() { println!("{}", line); }
 
 */
 
+#[derive(Clone)]
 pub struct Line {
     chars: Vec<char>,
+    /// how far wrapped rows after the first are indented
+    indent: usize,
     width: Option<usize>,
     nchars: Option<usize>,
     fdirs: Vec<Fmtr>,
@@ -122,6 +139,7 @@ impl Line {
     pub fn new() -> Line {
         Line {
             chars: Vec::new(),
+            indent: 0,
             width: None,
             nchars: None,
             fdirs: Vec::new(),
@@ -133,6 +151,24 @@ impl Line {
     /** Return the number of characters in the `Line`. */
     pub fn len(&self) -> usize { self.chars.len() }
     
+    /** Return whether the `Line` has no characters. */
+    pub fn is_empty(&self) -> bool { self.chars.is_empty() }
+    
+    /** Return the text of the `Line`, without any styling. */
+    pub fn plain(&self) -> String { self.chars.iter().collect() }
+    
+    /** Return how far rows after the first are indented when wrapped. */
+    pub fn indent(&self) -> usize { self.indent }
+    
+    /** Set how far rows after the first should be indented when the `Line`
+    is wrapped (so they can line up under, say, the text after a name).
+    The indent is ignored at widths less than twice it.
+    */
+    pub fn set_indent(&mut self, n: usize) {
+        self.width = None;
+        self.indent = n;
+    }
+    
     /** Add a chunk of unformatted text to the end of the `Line`. */
     pub fn push<T: AsRef<str>>(&mut self, s: T) {
         self.width = None;
@@ -166,18 +202,81 @@ impl Line {
         }
     }
     
+    /** Return the indices at which (non-overlapping) occurrences of `pat`
+    start. If `pat` is all lowercase, matching ignores case.
+    */
+    pub fn find(&self, pat: &[char]) -> Vec<usize> {
+        let mut v: Vec<usize> = Vec::new();
+        if pat.len() == 0 { return v; }
+        let fold = !pat.iter().any(|c| c.is_uppercase());
+        let same = |a: char, b: char| {
+            a == b || (fold && a.to_lowercase().eq(b.to_lowercase()))
+        };
+        
+        let mut i: usize = 0;
+        while i + pat.len() <= self.chars.len() {
+            if self.chars[i..].iter().zip(pat.iter()).all(|(a, b)| same(*a, *b)) {
+                v.push(i);
+                i += pat.len();
+            } else {
+                i += 1;
+            }
+        }
+        return v;
+    }
+    
+    /** Return a copy of the `Line` with the runs of `len` characters
+    starting at each of `starts` (in order, and not overlapping) set in
+    the style `styl`. Each run is followed by whatever style was in effect
+    before it.
+    */
+    pub fn highlighted(&self, starts: &[usize], len: usize, styl: &Style) -> Line {
+        let mut new = Line::new();
+        new.chars = self.chars.clone();
+        new.indent = self.indent;
+        
+        let mut old = self.fdirs.iter().peekable();
+        let mut current: Option<&Style> = None;
+        for &start in starts.iter() {
+            let end = start + len;
+            while let Some(f) = old.peek() {
+                if f.idx > start { break; }
+                new.fdirs.push((*f).clone());
+                current = Some(&f.code);
+                old.next();
+            }
+            new.fdirs.push(Fmtr::new(start, styl));
+            /* Directives inside the run would spoil it, but the last one
+            is where the style after the run comes from. */
+            while let Some(f) = old.peek() {
+                if f.idx >= end { break; }
+                current = Some(&f.code);
+                old.next();
+            }
+            new.fdirs.push(Fmtr::new(end, &NOSTYLE));
+            if let Some(c) = current { new.fdirs.push(Fmtr::new(end, c)); }
+        }
+        for f in old { new.fdirs.push(f.clone()); }
+        
+        return new;
+    }
+    
     fn wrap(&mut self, tgt: usize) {
         
+        let indent = if self.indent * 2 <= tgt { self.indent } else { 0 };
         let mut wraps: Vec<usize> = Vec::with_capacity(1 + self.chars.len() / tgt);
         let mut x: usize = 0;
         let mut lws: usize = 0;
         let mut write_leading_ws: bool = true;
+        /* Rows after the first have room for less, by the indent. */
+        let mut row_tgt = tgt;
         
         trace!("chars: {}", &(self.chars.iter().collect::<String>()));
         
         for (i, c) in self.chars.iter().enumerate() {
-            if x == tgt {
-                if i - tgt >= lws {
+            if x >= row_tgt {
+                row_tgt = tgt - indent;
+                if i - x >= lws {
                     wraps.push(i);
                     x = 0;
                 } else {
@@ -190,10 +289,10 @@ impl Line {
             if c.is_whitespace() {
                 lws = i;
                 if x > 0 || write_leading_ws {
-                    x = x + 1;
+                    x += 1;
                 }
             } else {
-                x = x + 1;
+                x += 1;
             }
         }
         
@@ -206,34 +305,36 @@ impl Line {
         write_leading_ws = true;
         let mut wrap_idx: usize = 0;
         let mut line_len: usize = 0;
+        /* the style in effect, so it can be picked up again after an indent */
+        let mut cur_code: Option<&Style> = None;
         
         for (i, c) in self.chars.iter().enumerate() {
-            if wrap_idx < wraps.len() {
-                if wraps[wrap_idx] == i {
-                    self.render.push(cur_line);
-                    cur_line = String::with_capacity(tgt);
-                    write_leading_ws = false;
-                    wrap_idx += 1;
-                    line_len = 0;
+            if wrap_idx < wraps.len() && wraps[wrap_idx] == i {
+                self.render.push(cur_line);
+                cur_line = String::with_capacity(tgt);
+                if indent > 0 {
+                    cur_line.push_str(&NOSTYLE);
+                    for _ in 0..indent { cur_line.push(' '); }
+                    if let Some(code) = cur_code { cur_line.push_str(code); }
                 }
+                write_leading_ws = false;
+                wrap_idx += 1;
+                line_len = 0;
             }
             
             while match nextf {
-                None => false,
-                Some(f) => {
-                    if f.idx == i {
-                        cur_line.push_str(&f.code);
-                        nextf = fmt_iter.next();
-                        true
-                    } else {
-                        false
-                    }
+                Some(f) if f.idx == i => {
+                    cur_line.push_str(&f.code);
+                    cur_code = Some(&f.code);
+                    nextf = fmt_iter.next();
+                    true
                 },
+                _ => false,
             } {}
             
             if line_len > 0 || write_leading_ws || !c.is_whitespace() {
                 cur_line.push(*c);
-                line_len = line_len + 1;
+                line_len += 1;
             }
         }
         
@@ -265,16 +366,12 @@ impl Line {
         
         for (i, c) in self.chars[..n].iter().enumerate() {
             while match nextf {
-                None => false,
-                Some(f) => {
-                    if f.idx == i {
-                        s.push_str(&f.code);
-                        nextf = fmt_iter.next();
-                        true
-                    } else {
-                        false
-                    }
+                Some(f) if f.idx == i => {
+                    s.push_str(&f.code);
+                    nextf = fmt_iter.next();
+                    true
                 },
+                _ => false,
             } {}
             s.push(*c);
         }
@@ -304,6 +401,10 @@ impl Line {
     }
 }
 
+impl Default for Line {
+    fn default() -> Line { Line::new() }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -316,7 +417,7 @@ mod test {
         s = s.chars().map(|c| {
             match c {
                 NEWLINE => SPACE,
-                a @ _ => a,
+                a => a,
             }
         }).collect();
         return s;
@@ -326,16 +427,51 @@ mod test {
     fn test_wrap() {
         for fname in &["test_strs/first.txt", "test_strs/second.txt"] {
             let mut el: Line = Line::new();
-            el.push(&rnr(fname));
+            el.push(rnr(fname));
             
             for n in &[36usize, 50, 60] {
                 for _ in 0..*n { print!("-"); }
-                println!("");
+                println!();
                 for line in el.lines(*n) { println!("{}", &line); }
             }
         }
     }
     
+    #[test]
+    fn test_find() {
+        let bold = Style::new(None, None, Some(&[crossterm::style::Attribute::Bold]));
+        let mut x = Line::new();
+        x.pushf("Grel Dude", &bold);
+        x.push(": grel grel GREL");
+        
+        let pat: Vec<char> = "grel".chars().collect();
+        assert_eq!(x.find(&pat), vec![0, 11, 16, 21]);
+        let pat: Vec<char> = "Grel".chars().collect();
+        assert_eq!(x.find(&pat), vec![0]);
+        
+        let mut y = x.highlighted(&x.find(&pat), pat.len(), &bold);
+        assert_eq!(y.len(), x.len());
+        assert_eq!(y.lines(60).len(), 1);
+    }
+    
+    #[test]
+    fn test_indent() {
+        let mut x = Line::new();
+        x.push("[12:00] Grel Dude: ");
+        x.set_indent(x.len());
+        x.push("grel grel grel grel grel grel grel grel grel grel grel");
+        
+        let rows = x.lines(40);
+        assert!(rows.len() > 1);
+        for row in rows[1..].iter() {
+            let text: String = row.chars().filter(|c| !c.is_control()).collect();
+            assert!(text.contains(&format!("{}grel", " ".repeat(19))));
+        }
+        /* Too narrow to bother indenting. */
+        let rows = x.lines(30);
+        assert!(rows[1..].iter().all(|r| r.contains("grel") && !r.contains("  ")));
+    }
+    
     #[test]
     fn test_color() {
         use crossterm::style::{Color, Attribute};
@@ -357,7 +493,7 @@ mod test {
         
         for n in &[36usize, 50, 60] {
             for _ in 0..*n { print!("-"); }
-            println!("");
+            println!();
             for line in x.lines(*n) { println!("{}", &line); }
         }
     }
@@ -0,0 +1,265 @@
+/*!
+Matching names against patterns, for the `who` and `rooms` queries.
+
+A query argument is a pattern, optionally preceded by options, each a
+whitespace-separated token:
+
+  * `-p` (the default) matches names that begin with the pattern
+  * `-s` matches names that contain the pattern anywhere
+  * `-g` treats the pattern as a glob, where `*` matches any run of
+    characters and `?` matches any single character (a pattern with
+    either of those in it is a glob even without `-g`)
+  * `-f` matches names that contain the characters of the pattern in
+    order, with anything in between, ranking closer matches higher
+  * `-r` (the default) sorts the results from best match to worst
+  * `-n` sorts the results by name
+
+Like names, patterns are matched case-and-whitespace-insensitively (that
+is, both are `ascollapse()`d).
+
+There's also `mentions()`, which the client uses to spot its user's name
+(and highlight words) in chat.
+*/
+use std::cmp::Ordering;
+
+use crate::user::ascollapse;
+
+/** How a `Pattern` matches names. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode { Prefix, Substring, Glob, Fuzzy, }
+
+/** How the names matched by a `Pattern` are sorted. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Order { Relevance, Name, }
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    pub mode: Mode,
+    pub order: Order,
+    /// collapsed
+    pub text: String,
+}
+
+impl Pattern {
+    /** Parse a query argument (as described above) into a `Pattern`. */
+    pub fn parse(arg: &str) -> Result<Pattern, String> {
+        let mut mode: Option<Mode> = None;
+        let mut order = Order::Relevance;
+        let mut rest = arg.trim_start();
+        
+        loop {
+            let tok = rest.split_whitespace().next().unwrap_or("");
+            if tok.len() != 2 || !tok.starts_with('-') { break; }
+            match tok {
+                "-p" => { mode = Some(Mode::Prefix); },
+                "-s" => { mode = Some(Mode::Substring); },
+                "-g" => { mode = Some(Mode::Glob); },
+                "-f" => { mode = Some(Mode::Fuzzy); },
+                "-r" => { order = Order::Relevance; },
+                "-n" => { order = Order::Name; },
+                _ => { return Err(format!("Unrecognized match option \"{}\".", tok)); },
+            }
+            rest = rest[tok.len()..].trim_start();
+        }
+        
+        let text = ascollapse(rest);
+        let mode = match mode {
+            Some(m) => m,
+            None if text.contains(['*', '?']) => Mode::Glob,
+            None => Mode::Prefix,
+        };
+        Ok(Pattern { mode, order, text })
+    }
+    
+    /** If the (already collapsed) `name` matches, return how well it
+    matches; higher is better. */
+    pub fn score(&self, name: &str) -> Option<i64> {
+        let extra = name.chars().count() as i64 - self.text.chars().count() as i64;
+        match self.mode {
+            /* Shorter names match "better", so an exact match comes first. */
+            Mode::Prefix => {
+                if name.starts_with(&self.text) { Some(-extra) } else { None }
+            },
+            Mode::Substring => {
+                name.find(&self.text).map(|n| {
+                    let pos = name[..n].chars().count() as i64;
+                    -(pos * 256 + extra)
+                })
+            },
+            Mode::Glob => {
+                let pat: Vec<char> = self.text.chars().collect();
+                let s: Vec<char> = name.chars().collect();
+                if glob(&pat, &s) { Some(0) } else { None }
+            },
+            Mode::Fuzzy => fuzzy(&self.text, name),
+        }
+    }
+    
+    /** Return the `names` that match, in order, keeping at most `limit` of
+    them; also returns how many matched in total.
+    */
+    pub fn find<'a, I>(&self, names: I, limit: usize) -> (Vec<String>, usize)
+    where I: IntoIterator<Item = &'a String>
+    {
+        let mut scored: Vec<(i64, &String)> = names.into_iter()
+            .filter_map(|n| self.score(n).map(|s| (s, n)))
+            .collect();
+        let total = scored.len();
+        
+        match self.order {
+            Order::Relevance => scored.sort_by(|a, b| {
+                match b.0.cmp(&a.0) {
+                    Ordering::Equal => a.1.cmp(b.1),
+                    x => x,
+                }
+            }),
+            Order::Name => scored.sort_by(|a, b| a.1.cmp(b.1)),
+        }
+        scored.truncate(limit);
+        
+        let v: Vec<String> = scored.into_iter().map(|(_, n)| n.clone()).collect();
+        return (v, total);
+    }
+}
+
+/* Match `s` against the glob `pat`. Whenever a `*` turns out to have matched
+too little, back up and let it swallow one more character.
+*/
+fn glob(pat: &[char], s: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    
+    while n < s.len() {
+        if p < pat.len() && (pat[p] == '?' || pat[p] == s[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pat.len() && pat[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    while p < pat.len() && pat[p] == '*' { p += 1; }
+    return p == pat.len();
+}
+
+/* Find the characters of `pat` in `name`, in order, taking the first
+occurrence of each. Each character found scores points, with a bonus for
+following right after the previous one (or for being at the very start);
+characters skipped over cost a point apiece.
+*/
+fn fuzzy(pat: &str, name: &str) -> Option<i64> {
+    let mut score: i64 = 0;
+    let mut last: Option<usize> = None;
+    let mut chars = name.chars().enumerate();
+    
+    for pc in pat.chars() {
+        let n = loop {
+            match chars.next() {
+                Some((n, c)) if c == pc => { break n; },
+                Some(_) => { score -= 1; },
+                None => { return None; },
+            }
+        };
+        score += 16;
+        let adjacent = match last {
+            Some(m) => n == m + 1,
+            None => n == 0,
+        };
+        if adjacent { score += 8; }
+        last = Some(n);
+    }
+    return Some(score);
+}
+
+/** Return whether `text` mentions `word` as a whole word (or run of whole
+words). `text` is split into words at anything that isn't alphanumeric,
+and `word` is mentioned if some run of consecutive words, `ascollapse()`d
+and strung together, is the same as `word` (collapsed, and with anything
+but alphanumerics dropped). So "Al" is mentioned in "hi, al!" but not in
+"really", and "Big Bob" in both "bigbob?" and "Big Bob's here".
+*/
+pub fn mentions(text: &str, word: &str) -> bool {
+    let target: String = ascollapse(word).chars()
+        .filter(|c| c.is_alphanumeric()).collect();
+    if target.len() == 0 { return false; }
+    
+    let words: Vec<String> = text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 0)
+        .map(ascollapse)
+        .collect();
+    for n in 0..words.len() {
+        let mut run = String::new();
+        for w in words[n..].iter() {
+            run.push_str(w);
+            if run.len() >= target.len() { break; }
+        }
+        if run == target { return true; }
+    }
+    return false;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    fn names(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+    
+    #[test]
+    fn parse_options() {
+        let p = Pattern::parse("Bob").unwrap();
+        assert_eq!((p.mode, p.order, p.text.as_str()), (Mode::Prefix, Order::Relevance, "bob"));
+        let p = Pattern::parse("-s -n Big Bob").unwrap();
+        assert_eq!((p.mode, p.order, p.text.as_str()), (Mode::Substring, Order::Name, "bigbob"));
+        assert_eq!(Pattern::parse("b*b").unwrap().mode, Mode::Glob);
+        assert_eq!(Pattern::parse("-f").unwrap().text, "");
+        assert_eq!(Pattern::parse("-bob").unwrap().text, "-bob");
+        assert!(Pattern::parse("-x bob").is_err());
+    }
+    
+    #[test]
+    fn modes() {
+        let v = names(&["bob", "bobby", "jimbob", "barb", "robert"]);
+        
+        let (m, n) = Pattern::parse("bob").unwrap().find(&v, 10);
+        assert_eq!((m, n), (names(&["bob", "bobby"]), 2));
+        
+        let (m, _) = Pattern::parse("-s bob").unwrap().find(&v, 10);
+        assert_eq!(m, names(&["bob", "bobby", "jimbob"]));
+        
+        let (m, _) = Pattern::parse("b*b").unwrap().find(&v, 10);
+        assert_eq!(m, names(&["barb", "bob"]));
+        let (m, _) = Pattern::parse("?ob*").unwrap().find(&v, 10);
+        assert_eq!(m, names(&["bob", "bobby", "robert"]));
+        
+        let (m, _) = Pattern::parse("-f bb").unwrap().find(&v, 10);
+        assert_eq!(m, names(&["bob", "bobby", "barb", "jimbob"]));
+    }
+    
+    #[test]
+    fn order_and_limit() {
+        let v = names(&["zed", "zebra", "ze"]);
+        let (m, n) = Pattern::parse("ze").unwrap().find(&v, 2);
+        assert_eq!((m, n), (names(&["ze", "zed"]), 3));
+        let (m, _) = Pattern::parse("-n -s e").unwrap().find(&v, 10);
+        assert_eq!(m, names(&["ze", "zebra", "zed"]));
+    }
+    
+    #[test]
+    fn mention_words() {
+        assert!(mentions("hi, Al!", "al"));
+        assert!(!mentions("really, it's all fine", "al"));
+        assert!(mentions("Big Bob's here", "Big Bob"));
+        assert!(mentions("bigbob?", "big bob"));
+        assert!(!mentions("bigbobby", "big bob"));
+        assert!(mentions("ping a/b", "a/b"));
+        assert!(!mentions("anything", ""));
+    }
+}
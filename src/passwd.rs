@@ -0,0 +1,64 @@
+/*!
+Salted password hashing, used for `Room` keys and registered names'
+passwords.
+
+Hashes are produced by
+[`rust-argon2`](https://docs.rs/rust-argon2/0.8.3/argon2/) in its
+self-describing "encoded" form, which carries the salt and parameters
+along with the hash itself, so a single `String` is all that needs to
+be stored.
+*/
+
+const SALT_LEN: usize = 16;
+
+/* Hashing cost. Keys and passwords are checked on `greld`'s main thread,
+so this is kept modest (argon2id, 4 MiB, three passes: about 20 ms in a
+release build), and `greld` limits how often each `User` may try one. Hashes
+carry their own parameters, so changing these doesn't invalidate old ones.
+*/
+const MEM_COST_KIB: u32 = 4096;
+const TIME_COST: u32 = 3;
+
+fn config() -> argon2::Config<'static> {
+    argon2::Config {
+        variant: argon2::Variant::Argon2id,
+        mem_cost: MEM_COST_KIB,
+        time_cost: TIME_COST,
+        lanes: 1,
+        thread_mode: argon2::ThreadMode::Sequential,
+        hash_length: 32,
+        ..argon2::Config::default()
+    }
+}
+
+/** Hash the supplied password with a freshly-generated random salt. */
+pub fn hash(pass: &str) -> Result<String, String> {
+    let mut salt = [0u8; SALT_LEN];
+    if let Err(e) = getrandom::getrandom(&mut salt) {
+        return Err(format!("Unable to generate salt: {}", e));
+    }
+    
+    match argon2::hash_encoded(pass.as_bytes(), &salt, &config()) {
+        Ok(h) => Ok(h),
+        Err(e) => Err(format!("Unable to hash password: {}", e)),
+    }
+}
+
+/** Return whether `pass` matches the previously-`hash()`ed `encoded`. */
+pub fn verify(encoded: &str, pass: &str) -> bool {
+    argon2::verify_encoded(encoded, pass.as_bytes()).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    #[test]
+    fn hash_and_verify() {
+        let h = hash("Sweet Ascension").unwrap();
+        assert!(verify(&h, "Sweet Ascension"));
+        assert!(!verify(&h, "sweet ascension"));
+        assert!(!verify("not a hash", "Sweet Ascension"));
+        assert_ne!(h, hash("Sweet Ascension").unwrap());
+    }
+}
@@ -8,6 +8,7 @@ allocation when creating structs to send.
 2020-02-01
 */
 
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 
 /** The `SndOp` enum represents on eof the `Room` operator subcommands.
@@ -19,17 +20,95 @@ pub enum SndOp<'a> {
     Open,
     /** Close the current `Room` to anyone not specifically `Invite`d. */
     Close,
+    /** Remove the `User` with the supplied name from the `Room`, sending
+    him back to the lobby. He is free to return. */
+    Kick(&'a str),
     /** Ban the `User` with the supplied name from the `Room` (even if it's
     `Open`), removing him if he's currently in it. */
-    Kick(&'a str),
+    Ban(&'a str),
+    /** Prevent the named `User` from sending `Text` to the `Room`. */
+    Mute(&'a str),
+    /** Allow a `Mute`d `User` to speak again. */
+    Unmute(&'a str),
+    /** Lift a `Ban` on the named `User`. */
+    Unban(&'a str),
     /** Allow the named `User` to enter the current room, even if it's `Close`d.
     Also sends an invitation message to the invited `User`. */
     Invite(&'a str),
-    /** Transfer operatorship to another `User`. (The `User` must be in the
-    `Room` in order to receive the mantle of ophood. */
+    /** Revoke an `Invite`. */
+    Uninvite(&'a str),
+    /** Set (or change) the current `Room`'s key, which will admit anyone
+    who supplies it, even if the `Room` is `Close`d. */
+    Key(&'a str),
+    /** Remove the current `Room`'s key. */
+    Unkey,
+    /** Set the current `Room`'s topic (an empty string removes it). */
+    Topic(&'a str),
+    /** Limit the current `Room` to the given number of `User`s (0 removes
+    any limit). Moderators and above are exempt. */
+    Limit(u32),
+    /** Require `User`s to wait the given number of seconds between
+    `Text`s in the current `Room` (0 turns slow mode off). Moderators and
+    above are exempt. */
+    Slow(u32),
+    /** Request the list of `User`s banned from the current `Room`. */
+    Bans,
+    /** Request the list of `User`s invited to the current `Room`. */
+    Invites,
+    /** Make the named `User` an operator of the `Room`. */
+    Operator(&'a str),
+    /** Make the named `User` a moderator of the `Room`. */
+    Moderator(&'a str),
+    /** Strip the named `User` of any operator or moderator rank. */
+    Demote(&'a str),
+    /** Transfer ownership to another `User`. (The `User` must be in the
+    `Room` in order to receive the mantle of ownership.) The current owner
+    becomes an operator. */
     Give(&'a str),
 }
 
+/** The `SndAdm` enum represents one of the server administrator
+commands. It is used in the `Sndr::Admin(...)` enum variant.
+
+Every variant except `Auth` requires that the sending `User` has already
+successfully `Auth`ed. Users and rooms are named by anything that
+collapses to their collapsed names.
+*/
+#[derive(Clone, Copy, Debug, Serialize)]
+pub enum SndAdm<'a> {
+    /** Authenticate as one of the server's configured administrators. */
+    Auth { name: &'a str, password: &'a str, },
+    /** Disconnect the named `User` from the server. */
+    Kill { who: &'a str, why: &'a str, },
+    /** Disconnect the named `User` and refuse further connections from
+    his or her address. */
+    Ban { who: &'a str, why: &'a str, },
+    /** Lift a server-wide ban on the given address. */
+    Unban(&'a str),
+    /** Request the list of server-wide bans. */
+    Bans,
+    /** Close the named `Room` (as with `Op(Close)`). */
+    Close(&'a str),
+    /** Send everyone in the named `Room` to the lobby, which causes the
+    (now empty) `Room` to wink out of existence. */
+    Destroy { room: &'a str, why: &'a str, },
+    /** Change the named `User`'s name. */
+    Rename { who: &'a str, name: &'a str, },
+    /** Make the named `User` (who must be in it) the owner of the named
+    `Room`. */
+    Owner { room: &'a str, who: &'a str, },
+    /** Send a message to every `User` on the server. */
+    Broadcast(&'a str),
+    /** Shut the server down after the given number of seconds, warning
+    everyone periodically until then. `why` is included in the warnings
+    and the final `Logout`. */
+    Shutdown { secs: u32, why: &'a str, },
+    /** Call off a pending `Shutdown`. */
+    Cancel,
+    /** Reread the server's configuration file, as on `SIGHUP`. */
+    Reload,
+}
+
 /** The `Sndr` enum is the structure that gets serialized to JSON and passed
 along the TCP connections between the server and the various clients.
 
@@ -37,8 +116,9 @@ The first four variants, `Text {...}`, `Ping`, `Priv {...}` and `Logout(...)`
 are bi-directional, being used to send similar information both from the
 client to the server and vice-versa.
 
-The next six, `Name`, `Join`, `Query`, `Block`, `Unblock, and `Op`, are
-for sending commands or requests from the client to the server.
+The next twelve, `Name`, `Join`, `JoinKey`, `Query`, `Block`, `Unblock`,
+`Register`, `Identify`, `Memo`, `Away`, `Op`, and `Admin`, are for
+sending commands or requests from the client to the server.
 
 The final three, `Info`, `Err`, and `Misc` are used only to send information
 from the server back to the client.
@@ -92,6 +172,10 @@ pub enum Sndr<'a> {
     /** Request to join (creating if necessary) a room. */
     Join(&'a str),
     
+    /** Request to join a room, supplying its key. A correct key will get
+    the `User` in even if the room is closed. */
+    JoinKey { room: &'a str, key: &'a str, },
+    
     /** A request from the client to the server for some type of information,
     like a list of users matching a pattern.
     
@@ -111,7 +195,8 @@ pub enum Sndr<'a> {
     };
     
     // Request for a list of `User` ID strs that begin with the supplied
-    // pattern.
+    // pattern. The pattern may be preceded by options to match substrings,
+    // globs, or fuzzily, and to sort by name; see the `matching` module.
     Query {
         what: "who",
         arg: "xxxhead", // pattern to match case-and-whitespace-insensitively
@@ -122,6 +207,13 @@ pub enum Sndr<'a> {
         what: "rooms",
         arg: "froglovers", // pattern to match (as before)
     };
+    
+    // Request details about a `User`: their `Room`, how long they've been
+    // connected, &c. (The requesting `User`'s own details if `arg` is empty.)
+    Query {
+        what: "whois",
+        arg: "somedude", // case-and-whitespace-insensitive name
+    };
     ```
     */
     Query { what: &'a str, arg: &'a str, },
@@ -133,9 +225,31 @@ pub enum Sndr<'a> {
     /** Client request to unblock the given user. */
     Unblock(&'a str),
     
+    /** Request to register the `User`'s current name, protected by the
+    given password. Registered names (and the room ranks and blocks that
+    go with them) survive server restarts. */
+    Register(&'a str),
+    
+    /** Prove ownership of a registered name, taking that name if the
+    `User` isn't already using it. */
+    Identify { name: &'a str, password: &'a str, },
+    
+    /** Leave a message for a `User` who isn't logged on, to be delivered
+    when someone with that (collapsed) name next logs on, or when its
+    owner identifies if it's a registered name. */
+    Memo { who: &'a str, text: &'a str, },
+    
+    /** Mark the `User` as away, with the given message (which anyone who
+    sends him or her a `Priv` gets as a reply). An empty message marks the
+    `User` as back. */
+    Away(&'a str),
+    
     /** One of the operator subcommands (see the `SndOp` enum). */
     Op(SndOp<'a>),
     
+    /** One of the server administrator commands (see the `SndAdm` enum). */
+    Admin(SndAdm<'a>),
+    
     
     //
     // Server-to-client messages
@@ -160,11 +274,29 @@ pub enum Sndr<'a> {
     Current `Misc` variants (with example field values):
     
     ``` ignore
-    // in response to a `Query { what: "roster", ... }`
+    // in response to a `Query { what: "roster", ... }`; `data` is a
+    // series of (rank marker, name, away message) triples, highest ranks
+    // first. Markers are "~" (owner), "@" (operator), "%" (moderator), or
+    // "" (member); the away message is "" for users who aren't away.
     Misc {
         what: "roster",
-        data: &["user1", "user2", "user7"], # ...
-        alt:  "Gay Space Communism roster: user1 (operator) user2, user7...",
+        data: &["~", "user1", "", "@", "user2", "lunch", "", "user7", ""], # ...
+        alt:  "Gay Space Communism roster: ~user1, @user2 (away), user7...",
+    };
+    
+    // when a user in the current `Room` goes away (with the given message)
+    // or comes back (with an empty message)
+    Misc {
+        what: "away",
+        data: &["Some Dude", "lunch"],
+        alt:  "Some Dude is away: lunch",
+    };
+    
+    // in reply to a `Priv` sent to a user who is away
+    Misc {
+        what: "away_reply",
+        data: &["Some Dude", "lunch"],
+        alt:  "Some Dude is away: lunch",
     };
     
     // when a user joins a `Room`
@@ -202,11 +334,18 @@ pub enum Sndr<'a> {
         alt:  "\"Guy's Old Name\" is now known as \"Guy's New Name\".",
     };
     
-    // when the `Room` operator changes
+    // when the `Room` owner changes
     Misc {
         what: "new_op",
         data: &["Some Other Gal", "Gay Space Communism"],
-        alt:  "Some Other Gal is now the operator of Gay Space Communism.",
+        alt:  "Some Other Gal is now the owner of Gay Space Communism.",
+    };
+    
+    // when a user's rank in the `Room` changes
+    Misc {
+        what: "role",
+        data: &["Some Dude", "moderator", "Gay Space Communism"],
+        alt:  "Some Dude is now a moderator of Gay Space Communism.",
     };
     
     // in response to a `Query { what: "addr", ... }`
@@ -216,19 +355,86 @@ pub enum Sndr<'a> {
         alt:  "Your public address is 127.0.0.1:12345.",
     };
     
-    // in response to a `Query { what: "who", arg: "head", }`
+    // in response to a `Query { what: "who", arg: "head", }`; `data` is
+    // a series of (collapsed name, away message) pairs, the away message
+    // being "" for users who aren't away
     Misc {
         what: "who",
-        data: &["Headmaster", "Head5h0t 360 420 69", "heading home | Fred"],
-        alt:  "Matching names: headmaster, head5h0t36042069, headinghome|fred",
+        data: &["headmaster", "", "head5h0t36042069", "lunch", "headinghome|fred", ""],
+        alt:  "Matching names: headmaster, head5h0t36042069 (away: lunch), headinghome|fred",
     };
     
-    // in response to a `Query { what: "rooms", arg: "gay", }`
+    // in response to a `Query { what: "rooms", arg: "gay", }`; `data` is
+    // a series of (name, collapsed name, number of users, owner, "open" or
+    // "closed", topic) sextuples, sorted by collapsed name. The owner and
+    // topic may be empty strings.
     Misc {
         what: "rooms",
-        data: &["Gay Space Communism", "Gay Hamster Fan Club",
-                "Gay Rights Advocacy", "Gaystation 3"],
-        alt:  "Matching Rooms: gayspacecommunism, gayhamsterfanclub, gayrightsadvocacy, gaystation3",
+        data: &["Gay Hamster Fan Club", "gayhamsterfanclub", "3", "Some Dude", "open", "",
+                "Gay Space Communism", "gayspacecommunism", "12", "Some Other Gal", "closed",
+                "Fully automated luxury"],
+        alt:  "Matching Rooms: Gay Hamster Fan Club (3), Gay Space Communism (12)",
+    };
+    
+    // in response to a `Query { what: "room_info", arg: "gay space", }`
+    // (or an empty `arg` for the current `Room`); after the room name,
+    // `data` is a series of (setting, value) pairs, with "none" for
+    // settings that aren't in effect. "slow" is in seconds.
+    Misc {
+        what: "room_info",
+        data: &["Gay Space Communism", "id", "gayspacecommunism", "users", "12",
+                "owner", "Some Other Gal", "closed", "yes", "key", "no",
+                "topic", "Fully automated luxury", "limit", "20", "slow", "none"],
+        alt:  "Gay Space Communism: id gayspacecommunism, users 12, owner Some Other Gal, ...",
+    };
+    
+    // in response to a `Query { what: "whois", arg: "some dude", }` (or an
+    // empty `arg` for yourself); after the user's name, `data` is a series
+    // of (item, value) pairs. "connected" and "last_text" (the last time
+    // the user said anything) are in seconds since the Unix epoch, and
+    // "account" is the registered name the user has identified as. Any of
+    // these may be "none". "address" is only sent to administrators.
+    Misc {
+        what: "whois",
+        data: &["Some Dude", "room", "Gay Space Communism", "connected", "1612130400",
+                "last_text", "1612137600", "away", "none", "account", "somedude"],
+        alt:  "Some Dude: room Gay Space Communism, connected 4h5m ago, last_text 2h5m ago, ...",
+    };
+    
+    // in response to an `Admin(Bans)`; `data` is a series of (address,
+    // name, banned by, time banned) quadruples, times in seconds since
+    // the Unix epoch.
+    Misc {
+        what: "server_bans",
+        data: &["10.0.0.7", "Annoying Guy", "root", "1612137600"],
+        alt:  "Banned from the server: 10.0.0.7 (Annoying Guy, by root, 2h5m ago)",
+    };
+    
+    // in response to an `Op(Bans)`; after the room name, `data` is a
+    // series of (banned user, banned by, time banned) triples, times in
+    // seconds since the Unix epoch. `Op(Invites)` gets an identically-
+    // structured response with `what: "invites"`.
+    Misc {
+        what: "bans",
+        data: &["Gay Space Communism", "Annoying Guy", "Some Other Gal", "1612137600"],
+        alt:  "Banned from Gay Space Communism: Annoying Guy (by Some Other Gal, 2h5m ago)",
+    };
+    
+    // a server-wide announcement, either from an administrator (whose
+    // name is the first datum) or from the server itself (in which case
+    // the first datum is an empty string)
+    Misc {
+        what: "announce",
+        data: &["Some Admin", "The server will restart in ten minutes."],
+        alt:  "Announcement from Some Admin: The server will restart in ten minutes.",
+    };
+    
+    // a `Memo` left while the recipient was offline; `data` is (sender,
+    // time sent in seconds since the Unix epoch, text)
+    Misc {
+        what: "memo",
+        data: &["Some Dude", "1612137600", "Call me when you get this."],
+        alt:  "Memo from Some Dude (2h5m ago): Call me when you get this.",
     };
     
     // echoes a `Priv` back to the sender
@@ -246,7 +452,7 @@ pub enum Sndr<'a> {
     },
 }
 
-impl<'a> Sndr<'_> {
+impl Sndr<'_> {
     /** Return the JSON-encoded bytes of the reciever. */
     pub fn bytes(&self) -> Vec<u8> {
         serde_json::to_vec_pretty(&self).unwrap()
@@ -262,10 +468,46 @@ pub enum RcvOp {
     Open,
     Close,
     Kick(String),
+    Ban(String),
+    Unban(String),
+    Mute(String),
+    Unmute(String),
     Invite(String),
+    Uninvite(String),
+    Key(String),
+    Unkey,
+    Topic(String),
+    Limit(u32),
+    Slow(u32),
+    Bans,
+    Invites,
+    Operator(String),
+    Moderator(String),
+    Demote(String),
     Give(String),
 }
 
+/** The data-owning counterpart to `SndAdm` that gets _deserialized_.
+
+    `&str`s have become `String`s, but their meanings are identical.
+*/
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub enum RcvAdm {
+    Auth { name: String, password: String, },
+    Kill { who: String, why: String, },
+    Ban { who: String, why: String, },
+    Unban(String),
+    Bans,
+    Close(String),
+    Destroy { room: String, why: String, },
+    Rename { who: String, name: String, },
+    Owner { room: String, who: String, },
+    Broadcast(String),
+    Shutdown { secs: u32, why: String, },
+    Cancel,
+    Reload,
+}
+
 /** The data-owning counterpart to `Sndr` that gets _deserialized_.
 All `&str`s become `String`s and `&[&str]`s become `Vec<String>`s.
 Otherwise, their structures and meanings are otherwise almost identical.
@@ -285,10 +527,16 @@ pub enum Rcvr {
     
     Name(String),
     Join(String),
+    JoinKey { room: String, key: String, },
     Query { what: String, arg: String, },
     Block(String),
     Unblock(String),
+    Register(String),
+    Identify { name: String, password: String, },
+    Memo { who: String, text: String, },
+    Away(String),
     Op(RcvOp),
+    Admin(RcvAdm),
     
     Info(String),
     Err(String),
@@ -301,13 +549,50 @@ impl Rcvr {
     limiting quota.
     */
     pub fn counts(&self) -> bool {
-        match self {
-            Rcvr::Text { who: _, lines: _ } => true,
-            Rcvr::Priv { who: _,  text: _ } => true,
-            Rcvr::Name(_) => true,
-            Rcvr::Join(_) => true,
-            _ => false,
-        }
+        matches!(self,
+            Rcvr::Text { who: _, lines: _ }
+            | Rcvr::Priv { who: _,  text: _ }
+            | Rcvr::Name(_)
+            | Rcvr::Join(_)
+            | Rcvr::JoinKey { room: _, key: _ }
+            /* Mostly so failed `Auth` and `Identify` attempts (and all the
+            password hashing) get throttled. */
+            | Rcvr::Register(_)
+            | Rcvr::Identify { name: _, password: _ }
+            | Rcvr::Memo { who: _, text: _ }
+            | Rcvr::Away(_)
+            | Rcvr::Admin(_)
+        )
+    }
+}
+
+/** Return the number of seconds between the Unix epoch and `t`. This is
+how times are represented in `Misc` data.
+*/
+pub fn unix_secs(t: SystemTime) -> u64 {
+    match t.duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    }
+}
+
+/** Describe roughly how long ago a time (in seconds since the Unix epoch)
+was, like "2h5m ago".
+*/
+pub fn describe_age(secs: u64) -> String {
+    let then = UNIX_EPOCH + Duration::from_secs(secs);
+    let d = match SystemTime::now().duration_since(then) {
+        Ok(d) => d.as_secs(),
+        Err(_) => 0,
+    };
+    if d < 60 {
+        format!("{}s ago", d)
+    } else if d < 3600 {
+        format!("{}m ago", d / 60)
+    } else if d < 86400 {
+        format!("{}h{}m ago", d / 3600, (d % 3600) / 60)
+    } else {
+        format!("{}d{}h ago", d / 86400, (d % 86400) / 3600)
     }
 }
 
@@ -322,8 +607,9 @@ pub enum End {
     Room(u64),
     /// the server (only messages _from_ the server)
     Server,
-    /// to everyone (it turns out this is as-of-yet unused, but it's
-    /// existence seems harmless, so it stays for now)
+    /// to everyone on the server; `Room`s don't deliver these, `greld`
+    /// hands them to every `User` directly (still subject to blocks if
+    /// the source is a `User`)
     All,
 }
 
@@ -359,7 +645,7 @@ impl<'a> Env {
 mod test {
     use super::*;
     
-    fn loose_test<'a>(m: &'a Sndr) {
+    fn loose_test(m: &Sndr) {
         let env = Env::new(End::All, End::All, m);
         let md = std::mem::discriminant(m);
         println!("{}\n", std::str::from_utf8(env.bytes()).unwrap());
@@ -389,6 +675,18 @@ mod test {
         };
         loose_test(&m);
         
+        println!("\n*::JoinKey variant");
+        let m = Sndr::JoinKey { room: "The Den", key: "hunter2" };
+        loose_test(&m);
+        
+        println!("\n*::Identify variant");
+        let m = Sndr::Identify { name: "Fps Doug", password: "hunter2" };
+        loose_test(&m);
+        
+        println!("\n*::Memo variant");
+        let m = Sndr::Memo { who: "naggum", text: "Call me when you get this." };
+        loose_test(&m);
+        
         println!("\n*::Logout variant");
         let m = Sndr::Logout("You have been logged out because everyone hates you.");
         loose_test(&m);
@@ -398,5 +696,11 @@ mod test {
         loose_test(&m);
         let m = Sndr::Op(SndOp::Kick("FpS DoUg"));
         loose_test(&m);
+        
+        println!("\n A couple of Admin variants");
+        let m = Sndr::Admin(SndAdm::Auth { name: "root", password: "hunter2" });
+        loose_test(&m);
+        let m = Sndr::Admin(SndAdm::Bans);
+        loose_test(&m);
     }
 }
@@ -7,11 +7,160 @@ A `Room` roughly parallels an IRC "channel". They can be formed on the
 fly from any valid, unique name, and are meant to automatically wink out of
 existence when the last person leaves.
 */
+/* `cfg(debug)` (set by hand with `--cfg debug`) turns on drop logging. */
+#![allow(unexpected_cfgs)]
 
 use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use super::proto3::{Env, End};
+use serde::{Deserialize, Serialize};
+
+use super::proto3::{Env, End, unix_secs};
 use super::user::{User, ascollapse};
+use super::passwd;
+use super::state::{MarkState, RankState, RoomState};
+
+/** A single thing a `User` with sufficient rank is allowed to do to a
+`Room` (or to the other `User`s in it). Each `Op` subcommand requires
+exactly one of these.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Perm {
+    /// open or close the `Room`, or set its key
+    Close,
+    /// remove a `User` from the `Room`
+    Kick,
+    /// prevent a `User` from sending `Text` to the `Room`
+    Mute,
+    /// remove a `User` and prevent him or her from returning
+    Ban,
+    /// permit a `User` to enter even when the `Room` is closed
+    Invite,
+    /// raise or lower the ranks of other `User`s
+    Promote,
+    /// hand ownership of the `Room` to someone else
+    Give,
+}
+
+static OWNER_PERMS: &[Perm] = &[Perm::Close, Perm::Kick, Perm::Mute, Perm::Ban,
+                                Perm::Invite, Perm::Promote, Perm::Give];
+static OPERATOR_PERMS: &[Perm] = &[Perm::Close, Perm::Kick, Perm::Mute, Perm::Ban,
+                                   Perm::Invite, Perm::Promote];
+static MODERATOR_PERMS: &[Perm] = &[Perm::Kick, Perm::Mute];
+static MEMBER_PERMS: &[Perm] = &[];
+
+/** The rank a `User` holds in a `Room`. Variants are declared from lowest
+to highest, so ranks can be compared with `<` and `>`.
+
+A `User` may only exercise a `Perm` over another `User` of strictly
+lower rank, and may only bestow ranks strictly lower than his or her own.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Member,
+    Moderator,
+    Operator,
+    Owner,
+}
+
+impl Role {
+    /** Return the set of `Perm`s granted to this rank. */
+    pub fn perms(&self) -> &'static [Perm] {
+        match self {
+            Role::Owner     => OWNER_PERMS,
+            Role::Operator  => OPERATOR_PERMS,
+            Role::Moderator => MODERATOR_PERMS,
+            Role::Member    => MEMBER_PERMS,
+        }
+    }
+    
+    /** Return whether this rank has the given `Perm`. */
+    pub fn can(&self, p: Perm) -> bool { self.perms().contains(&p) }
+    
+    /** The short marker used to indicate this rank in roster listings. */
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Role::Owner     => "~",
+            Role::Operator  => "@",
+            Role::Moderator => "%",
+            Role::Member    => "",
+        }
+    }
+    
+    /** A human-readable name for this rank. */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Owner     => "owner",
+            Role::Operator  => "operator",
+            Role::Moderator => "moderator",
+            Role::Member    => "member",
+        }
+    }
+}
+
+/** A record of a ban or an invitation: whom it concerns, who set it,
+and when.
+*/
+#[derive(Clone, Debug)]
+pub struct Mark {
+    /// user ID of the `User` banned or invited
+    pub uid: u64,
+    /// name of that `User` at the time
+    pub name: String,
+    /// collapsed version of `.name`, for matching
+    pub idstr: String,
+    /// name of the `User` who set the ban or invitation
+    pub by: String,
+    pub when: SystemTime,
+}
+
+impl Mark {
+    pub fn new(uid: u64, name: &str, by: &str) -> Mark {
+        Mark {
+            uid,
+            name: name.to_string(),
+            idstr: ascollapse(name),
+            by: by.to_string(),
+            when: SystemTime::now(),
+        }
+    }
+    
+    /** Return whether this `Mark` concerns the given `User`, either by
+    user ID or (for `User`s who may have since disconnected) by collapsed
+    name. A user ID of 0 (that of restored `Mark`s, and of `User`s who
+    aren't connected) matches nothing; those go by name alone.
+    */
+    pub fn matches(&self, uid: u64, idstr: &str) -> bool {
+        (self.uid != 0 && self.uid == uid) || self.idstr == idstr
+    }
+    
+    /** Return whether this `Mark` applies to the given `User`. `Mark`s
+    restored from saved state have a user ID of 0 and apply to anyone
+    with the right name; others apply only to the `User` they were set on.
+    */
+    pub fn applies(&self, uid: u64, idstr: &str) -> bool {
+        self.uid == uid || (self.uid == 0 && self.idstr == idstr)
+    }
+    
+    fn to_state(&self) -> MarkState {
+        MarkState {
+            name: self.name.clone(),
+            by: self.by.clone(),
+            when: unix_secs(self.when),
+        }
+    }
+    
+    fn from_state(st: &MarkState) -> Mark {
+        Mark {
+            uid: 0,
+            name: st.name.clone(),
+            idstr: ascollapse(&st.name),
+            by: st.by.clone(),
+            when: UNIX_EPOCH + Duration::from_secs(st.when),
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Room {
@@ -19,10 +168,28 @@ pub struct Room {
     name: String,
     idstr: String,
     users: Vec<u64>,
-    op: u64,
+    owner: u64,
+    ops: Vec<u64>,
+    mods: Vec<u64>,
     pub closed: bool,
-    bans: Vec<u64>,
-    invites: Vec<u64>,
+    topic: String,
+    key: Option<String>,
+    bans: Vec<Mark>,
+    invites: Vec<Mark>,
+    mutes: Vec<u64>,
+    limit: Option<usize>,
+    slow: Option<Duration>,
+    last_post: HashMap<u64, Instant>,
+    /* Registered names of the `User`s who have been in the `Room`, by
+    user ID, so their ranks can be saved. */
+    accounts: HashMap<u64, String>,
+    /* Ranks restored from saved state that haven't been claimed by their
+    registered names yet. */
+    claims: Vec<RankState>,
+    /* Whether anyone has joined since the `Room` was created. */
+    visited: bool,
+    /* Whether an administrator has destroyed the `Room`. */
+    destroyed: bool,
     inbox: Vec<Env>,
 }
 
@@ -33,26 +200,130 @@ impl Room {
             idstr: ascollapse(&new_name),
             name: new_name,
             users: Vec::new(),
-            op: creator_id,
+            owner: creator_id,
+            ops: Vec::new(),
+            mods: Vec::new(),
             closed: false,
+            topic: String::new(),
+            key: None,
             bans: Vec::new(),
             invites: Vec::new(),
+            mutes: Vec::new(),
+            limit: None,
+            slow: None,
+            last_post: HashMap::new(),
+            accounts: HashMap::new(),
+            claims: Vec::new(),
+            visited: false,
+            destroyed: false,
             inbox: Vec::new(),
         }
     }
     
+    /** Recreate a `Room` from saved state. If the saved state has an owner,
+    the `Room` has none until they `.claim()` it; otherwise `creator_id`
+    is the owner.
+    */
+    pub fn restore(id: u64, st: &RoomState, creator_id: u64) -> Room {
+        let owner = if st.has_owner() { 0 } else { creator_id };
+        let mut r = Room::new(id, st.name.clone(), owner);
+        r.closed = st.closed;
+        r.topic = st.topic.clone();
+        r.key = st.key.clone();
+        r.limit = st.limit;
+        r.slow = st.slow.map(Duration::from_secs);
+        r.claims = st.ranks.clone();
+        r.bans = st.bans.iter().map(Mark::from_state).collect();
+        r.invites = st.invites.iter().map(Mark::from_state).collect();
+        r
+    }
+    
+    /** Return the state of the `Room` worth saving. */
+    pub fn snapshot(&self) -> RoomState {
+        let mut ranks: Vec<RankState> = Vec::new();
+        let ranked = std::iter::once((self.owner, Role::Owner))
+            .chain(self.ops.iter().map(|n| (*n, Role::Operator)))
+            .chain(self.mods.iter().map(|n| (*n, Role::Moderator)));
+        for (uid, role) in ranked {
+            if let Some(a) = self.accounts.get(&uid) {
+                ranks.push(RankState { account: a.clone(), role });
+            }
+        }
+        for c in self.claims.iter() {
+            if !ranks.iter().any(|r| r.account == c.account) { ranks.push(c.clone()); }
+        }
+        
+        RoomState {
+            name: self.name.clone(),
+            closed: self.closed,
+            topic: self.topic.clone(),
+            key: self.key.clone(),
+            limit: self.limit,
+            slow: self.slow.map(|d| d.as_secs()),
+            ranks,
+            bans: self.bans.iter().map(Mark::to_state).collect(),
+            invites: self.invites.iter().map(Mark::to_state).collect(),
+        }
+    }
+    
+    /** Return whether anyone has joined the `Room` since it was created
+    (or restored). */
+    pub fn was_visited(&self) -> bool { self.visited }
+    
+    /** Mark the `Room` as destroyed: it's closed, and when it empties, it's
+    deleted rather than saved. */
+    pub fn destroy(&mut self) {
+        self.closed = true;
+        self.destroyed = true;
+    }
+    
+    /** Return whether the `Room` has been `.destroy()`ed. */
+    pub fn is_destroyed(&self) -> bool { self.destroyed }
+    
+    /** Return the unclaimed rank restored from saved state (if any) that
+    belongs to the given (collapsed) registered name. */
+    pub fn claim_of(&self, account: &str) -> Option<Role> {
+        self.claims.iter().find(|c| c.account == account).map(|c| c.role)
+    }
+    
+    /** Note that the `User` with the given ID has identified as the given
+    (collapsed) registered name. If a rank restored from saved state
+    belongs to that name, the `User` is given it, and it's returned.
+    */
+    pub fn claim(&mut self, uid: u64, account: &str) -> Option<Role> {
+        self.accounts.insert(uid, account.to_string());
+        let n = self.claims.iter().position(|c| c.account == account)?;
+        let role = self.claims.remove(n).role;
+        if role == Role::Owner {
+            self.set_owner(uid);
+        } else if role > self.role_of(uid) {
+            self.set_role(uid, role);
+        }
+        Some(role)
+    }
+    
     pub fn get_id(&self) -> u64 { self.idn }
     pub fn get_name(&self) -> &str { &(self.name) }
     pub fn get_idstr(&self) -> &str { &(self.idstr) }
+    /** The topic is an arbitrary line of text describing the `Room`; an
+    empty topic means there isn't one. */
+    pub fn get_topic(&self) -> &str { &(self.topic) }
+    pub fn set_topic(&mut self, new_topic: &str) {
+        self.topic = String::from(new_topic.trim());
+    }
     
     /** Deliver an `Env` to the appropriate `End`: either the entire
     `Room`, or if its `dest` field is `End::User(n)`, just that user.
+    
+    `Env`s addressed to `End::All` aren't the `Room`'s business and are
+    ignored.
     */
     pub fn deliver(&self, env: &Env, uid_hash: &mut HashMap<u64, User>) {
         match env.dest {
             End::User(uid) => {
                 if let Some(u) = uid_hash.get_mut(&uid) { u.deliver(env); }
             },
+            End::All => {},
             _ => {
                 for uid in &(self.users) {
                     if let Some(u) = uid_hash.get_mut(uid) { u.deliver(env); }
@@ -73,9 +344,10 @@ impl Room {
                 End::User(uid) => {
                     if let Some(u) = uid_hash.get_mut(&uid) { u.deliver(&env); }
                 },
+                End::All => {},
                 _ => {
                     for uid in &(self.users) {
-                        if let Some(u) = uid_hash.get_mut(&uid) { u.deliver(&env); }
+                        if let Some(u) = uid_hash.get_mut(uid) { u.deliver(&env); }
                     }
                 },
             }
@@ -83,48 +355,223 @@ impl Room {
     }
     
     /** Add the given user ID to the list of `User`s "in" the `Room`. */
-    pub fn join(&mut self, uid: u64) { self.users.push(uid); }
+    pub fn join(&mut self, uid: u64) {
+        self.users.push(uid);
+        self.accounts.remove(&uid);
+        self.visited = true;
+    }
     /** Remove the given user ID (if present) from the list of `User`s that
     are "in" the `Room` */
-    pub fn leave(&mut self, uid: u64) { self.users.retain(|n| *n != uid); }
+    pub fn leave(&mut self, uid: u64) {
+        self.users.retain(|n| *n != uid);
+        self.last_post.remove(&uid);
+    }
     
-    /** Add the given user ID to the list of `User`s who are "banned"
+    /** Add the given `Mark` to the list of `User`s who are "banned"
     (prohibited from entering) the `Room`.
     
     This also removes the given user ID from the list of "invited"
     (see `.invite()`, below) `User`s, if present.
     */
-    pub fn ban(&mut self, uid: u64) {
-        self.invites.retain(|n| *n != uid);
-        self.bans.push(uid);
+    pub fn ban(&mut self, mark: Mark) {
+        self.invites.retain(|m| !m.applies(mark.uid, &mark.idstr));
+        self.bans.push(mark);
     }
     
-    /** Add the given user ID to the list of `User`s "invited" to the
+    /** Add the given `Mark` to the list of `User`s "invited" to the
     `Room`, meaning that they may enter even if the operator has
     `.closed` it.
     
     This also removes the given user ID from the "banned" (see `.ban(...)`,
     above) list, if present.
     */
-    pub fn invite(&mut self, uid: u64) {
-        self.bans.retain(|n| *n != uid);
-        self.invites.push(uid);
+    pub fn invite(&mut self, mark: Mark) {
+        self.bans.retain(|m| !m.applies(mark.uid, &mark.idstr));
+        self.invites.push(mark);
+    }
+    
+    /** Lift the ban on the `User` with the given user ID or collapsed
+    name, returning the removed `Mark` (or `None` if there was no such ban).
+    */
+    pub fn unban(&mut self, uid: u64, idstr: &str) -> Option<Mark> {
+        let n = self.bans.iter().position(|m| m.matches(uid, idstr))?;
+        Some(self.bans.remove(n))
+    }
+    
+    /** Revoke the invitation of the `User` with the given user ID or
+    collapsed name, returning the removed `Mark` (or `None` if there was
+    no such invitation).
+    */
+    pub fn uninvite(&mut self, uid: u64, idstr: &str) -> Option<Mark> {
+        let n = self.invites.iter().position(|m| m.matches(uid, idstr))?;
+        Some(self.invites.remove(n))
+    }
+    
+    /** Set (or with `None`, remove) the key that allows entry to the
+    `Room` even when it's closed. The key is stored only in hashed form.
+    */
+    pub fn set_key(&mut self, key: Option<&str>) -> Result<(), String> {
+        self.key = match key {
+            None => None,
+            Some(k) => Some(passwd::hash(k)?),
+        };
+        Ok(())
+    }
+    
+    /** Return whether the `Room` has a key set. */
+    pub fn has_key(&self) -> bool { self.key.is_some() }
+    
+    /** Return whether `key` matches the `Room`'s key. Always false if the
+    `Room` has no key.
+    */
+    pub fn check_key(&self, key: &str) -> bool {
+        match &self.key {
+            None => false,
+            Some(h) => passwd::verify(h, key),
+        }
+    }
+    
+    /** Return the list of bans, oldest first. */
+    pub fn get_bans(&self) -> &[Mark] { &(self.bans) }
+    /** Return the list of invitations, oldest first. */
+    pub fn get_invites(&self) -> &[Mark] { &(self.invites) }
+    
+    /** Set the `User` with the given user ID to be the `Room`'s owner.
+    
+    The previous owner (if any) is demoted to operator.
+    */
+    pub fn set_owner(&mut self, uid: u64) {
+        let old = self.owner;
+        self.ops.retain(|n| *n != uid);
+        self.mods.retain(|n| *n != uid);
+        self.owner = uid;
+        if old != 0 && old != uid { self.ops.push(old); }
     }
+    /** Return the user ID of the `Room`'s current owner. */
+    pub fn get_owner(&self) -> u64 { self.owner }
     
-    /** Set the `User` with the given user ID to be the `Room`'s operator. */
-    pub fn set_op(&mut self, uid: u64) { self.op = uid; }
-    /** Return the user ID of the `Room`'s current operator. */
-    pub fn get_op(&self) -> u64 { self.op }
+    /** Return the rank of the `User` with the given ID in this `Room`. */
+    pub fn role_of(&self, uid: u64) -> Role {
+        if uid != 0 && uid == self.owner {
+            Role::Owner
+        } else if self.ops.contains(&uid) {
+            Role::Operator
+        } else if self.mods.contains(&uid) {
+            Role::Moderator
+        } else {
+            Role::Member
+        }
+    }
+    
+    /** Set the rank of the `User` with the given ID to `Operator`,
+    `Moderator`, or `Member`. Ownership can only be changed with
+    `.set_owner(...)` (above), so this does nothing to the owner or
+    when passed `Role::Owner`.
+    */
+    pub fn set_role(&mut self, uid: u64, role: Role) {
+        if uid == self.owner { return; }
+        self.ops.retain(|n| *n != uid);
+        self.mods.retain(|n| *n != uid);
+        match role {
+            Role::Operator  => { self.ops.push(uid); },
+            Role::Moderator => { self.mods.push(uid); },
+            _ => {},
+        }
+    }
+    
+    /** Return the user ID of the `User` present in the `Room` who should
+    inherit ownership if the owner leaves: the longest-serving operator,
+    failing that the longest-serving moderator, failing that whoever has
+    been in the `Room` longest. Nobody inherits a `Room` whose saved owner
+    has yet to come back and claim it.
+    */
+    pub fn successor(&self) -> Option<u64> {
+        if self.claims.iter().any(|c| c.role == Role::Owner) { return None; }
+        let present = |n: &&u64| self.users.contains(n);
+        if let Some(n) = self.ops.iter().find(present) { return Some(*n); }
+        if let Some(n) = self.mods.iter().find(present) { return Some(*n); }
+        self.users.first().copied()
+    }
+    
+    /** Prevent the `User` with the given ID from sending `Text` to the
+    `Room`. Returns false if that `User` was already muted.
+    */
+    pub fn mute(&mut self, uid: u64) -> bool {
+        if self.mutes.contains(&uid) { return false; }
+        self.mutes.push(uid);
+        true
+    }
+    
+    /** Allow a muted `User` to speak again. Returns false if that `User`
+    wasn't muted.
+    */
+    pub fn unmute(&mut self, uid: u64) -> bool {
+        let n = self.mutes.len();
+        self.mutes.retain(|x| *x != uid);
+        n != self.mutes.len()
+    }
+    
+    /** Set (or with `None`, remove) the maximum number of `User`s allowed
+    in the `Room` at once. Moderators and above are exempt.
+    */
+    pub fn set_limit(&mut self, limit: Option<usize>) { self.limit = limit; }
+    /** Return the maximum number of `User`s allowed in the `Room`, if any. */
+    pub fn get_limit(&self) -> Option<usize> { self.limit }
+    /** Return whether the `User` with the given ID should be refused entry
+    because the `Room` is at its limit. */
+    pub fn is_full_for(&self, uid: u64) -> bool {
+        match self.limit {
+            None => false,
+            Some(n) => self.users.len() >= n && self.role_of(uid) < Role::Moderator,
+        }
+    }
+    
+    /** Set (or with `None`, remove) the minimum interval between `Text`s
+    from each `User` ("slow mode"). Moderators and above are exempt.
+    */
+    pub fn set_slow(&mut self, slow: Option<Duration>) {
+        self.slow = slow;
+        self.last_post.clear();
+    }
+    /** Return the slow mode interval, if any. */
+    pub fn get_slow(&self) -> Option<Duration> { self.slow }
+    
+    /** Record that the `User` with the given ID is posting `Text` now. If
+    slow mode forbids this, nothing is recorded and the time remaining
+    until that `User` may post again is returned instead.
+    */
+    pub fn try_post(&mut self, uid: u64) -> Result<(), Duration> {
+        let slow = match self.slow {
+            None => { return Ok(()); },
+            Some(d) => d,
+        };
+        if self.role_of(uid) >= Role::Moderator { return Ok(()); }
+        
+        let now = Instant::now();
+        if let Some(t) = self.last_post.get(&uid) {
+            let since = now.duration_since(*t);
+            if since < slow { return Err(slow - since); }
+        }
+        self.last_post.insert(uid, now);
+        Ok(())
+    }
     
     /** Return the list of user IDs of `User`s in the `Room`. */
     pub fn get_users(&self) -> &[u64] { &(self.users) }
     
-    /** Return whether the `User` with the given ID is "banned" (see
-    `.ban(...)`, above). */
-    pub fn is_banned(&self, uid: &u64)  -> bool { self.bans.contains(uid) }
-    /** Return whether the `User` with the given ID is "invited" (see
-    `.invite(...)`, above). */
-    pub fn is_invited(&self, uid: &u64) -> bool { self.invites.contains(uid) }
+    /** Return whether the `User` with the given ID and collapsed name is
+    "banned" (see `.ban(...)`, above). */
+    pub fn is_banned(&self, uid: &u64, idstr: &str)  -> bool {
+        self.bans.iter().any(|m| m.applies(*uid, idstr))
+    }
+    /** Return whether the `User` with the given ID and collapsed name is
+    "invited" (see `.invite(...)`, above). */
+    pub fn is_invited(&self, uid: &u64, idstr: &str) -> bool {
+        self.invites.iter().any(|m| m.applies(*uid, idstr))
+    }
+    /** Return whether the `User` with the given ID is muted (see
+    `.mute(...)`, above). */
+    pub fn is_muted(&self, uid: &u64)   -> bool { self.mutes.contains(uid) }
 }
 
 #[cfg(debug)]
@@ -133,3 +580,66 @@ impl Drop for Room {
         println!("Room {} ({}) dropping.", self.idn, &(self.name));
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    #[test]
+    fn unban_restored() {
+        let mark = |name: &str| MarkState {
+            name: name.to_string(),
+            by: String::from("Op"),
+            when: 0,
+        };
+        let st = RoomState {
+            name: String::from("Lobby"),
+            closed: false,
+            topic: String::new(),
+            key: None,
+            limit: None,
+            slow: None,
+            ranks: Vec::new(),
+            bans: vec![mark("Alice"), mark("Bob")],
+            invites: vec![mark("Alice"), mark("Bob")],
+        };
+        let mut r = Room::restore(1, &st, 100);
+        
+        /* An offline target resolves to user ID 0. */
+        assert_eq!(r.unban(0, "bob").map(|m| m.name), Some(String::from("Bob")));
+        assert_eq!(r.uninvite(0, "bob").map(|m| m.name), Some(String::from("Bob")));
+        assert_eq!(r.get_bans().len(), 1);
+        assert_eq!(r.get_bans()[0].name, "Alice");
+        assert!(r.unban(0, "bob").is_none());
+        assert!(r.unban(0, "carol").is_none());
+        
+        r.ban(Mark::new(200, "Carol", "Op"));
+        assert!(r.unban(200, "someone_else").is_some());
+    }
+    
+    #[test]
+    fn restored_owner() {
+        let st = RoomState {
+            name: String::from("Den"),
+            closed: false,
+            topic: String::new(),
+            key: None,
+            limit: None,
+            slow: None,
+            ranks: vec![RankState { account: String::from("alice"), role: Role::Owner }],
+            bans: Vec::new(),
+            invites: Vec::new(),
+        };
+        let mut r = Room::restore(1, &st, 100);
+        r.join(100);
+        r.claim(100, "bob");
+        assert_eq!(r.role_of(100), Role::Member);
+        assert_eq!(r.successor(), None);
+        
+        r.join(200);
+        assert_eq!(r.claim(200, "alice"), Some(Role::Owner));
+        assert_eq!(r.role_of(200), Role::Owner);
+        assert_eq!(r.role_of(100), Role::Member);
+        assert_eq!(r.successor(), Some(100));
+    }
+}
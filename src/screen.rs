@@ -14,11 +14,22 @@ use std::io::{Write, Stdout};
 use crossterm::{QueueableCommand, cursor, style, terminal};
 
 use super::line::*;
+use super::user::ascollapse;
 
 const SPACE: char = ' ';
 const VBAR:  char = '│';
 const HBAR:  char = '—';
 
+/* Colors speakers' names are drawn from, chosen to be legible on both dark
+and light backgrounds. */
+const NICK_COLORS: &[u8] = &[
+    31, 32, 33, 37, 38, 39, 67, 68, 71, 72, 73, 74, 97, 98, 103, 107,
+    131, 133, 134, 136, 137, 139, 143, 167, 168, 169, 170, 172, 173, 175, 178, 179,
+];
+/* ...and the ones to use when only the basic 16 are available (leaving out
+black, white, and the greys). */
+const NICK_COLORS_16: &[u8] = &[1, 2, 3, 4, 5, 6, 9, 10, 11, 12, 13, 14];
+
 lazy_static!{
     static ref DEFAULT_DIM: Style =
         Style::new(Some(style::Color::AnsiValue(239)), None, None);
@@ -35,6 +46,20 @@ lazy_static!{
     static ref DEFAULT_HIGHLIGHT_BOLD: Style =
         Style::new(Some(style::Color::White), None, Some(&[style::Attribute::Bold]));
                            
+    static ref DEFAULT_ANNOUNCE: Style =
+        Style::new(Some(style::Color::White), None,
+                   Some(&[style::Attribute::Bold, style::Attribute::Reverse]));
+    
+    static ref DEFAULT_SEARCH: Style =
+        Style::new(Some(style::Color::Black), Some(style::Color::Yellow), None);
+    
+    static ref DEFAULT_MENTION: Style =
+        Style::new(Some(style::Color::Yellow), None, Some(&[style::Attribute::Bold]));
+    
+    static ref DEFAULT_NICKS: Vec<Style> = nick_styles(NICK_COLORS, false);
+    
+    static ref DEFAULT_PLAIN: Style = Style::new(None, None, None);
+    
     static ref DEFAULT_REVERSE: Style =
         Style::new(None, None, Some(&[style::Attribute::Reverse]));
     
@@ -54,12 +79,68 @@ lazy_static!{
 This helps maintain a theme, instead of just setting whatever colors and
 attributes wherever.
 */
+#[derive(Clone)]
 pub struct Styles {
     pub dim:       Style,
     pub dim_bold:  Style,
     pub bold:      Style,
     pub high:      Style,
     pub high_bold: Style,
+    /// server-wide announcements
+    pub announce:  Style,
+    /// matches of a scrollback search
+    pub search:    Style,
+    /// lines that mention the user (or one of the highlight words)
+    pub mention:   Style,
+    /// speakers' names, one of which is picked for each name
+    pub nicks:     Vec<Style>,
+    /// the text of the user's own messages
+    pub own:       Style,
+    /// the text of other people's messages
+    pub others:    Style,
+    /// the text of private messages
+    pub private:   Style,
+    /// informational messages from the server
+    pub info:      Style,
+    /// error messages
+    pub error:     Style,
+    /// the marker by operators' names in the roster
+    pub op:        Style,
+    /// text in the status bars
+    pub status:    Style,
+    /// the lines (`VBAR` and `HBAR`) separating the parts of the screen
+    pub separator: Style,
+    /// the input line
+    pub input:     Style,
+}
+
+impl Styles {
+    /** Return the style for the name of the speaker `name`. It's picked by
+    a (32-bit FNV-1a) hash of the collapsed name, so a given name always gets
+    the same color, however it's capitalized or spaced.
+    */
+    pub fn nick(&self, name: &str) -> &Style {
+        if self.nicks.is_empty() { return &self.high; }
+        let mut h: u32 = 0x811c9dc5;
+        for b in ascollapse(name).bytes() {
+            h ^= b as u32;
+            h = h.wrapping_mul(0x01000193);
+        }
+        return &self.nicks[(h as usize) % self.nicks.len()];
+    }
+}
+
+/* Return styles for speakers' names in each of the ANSI `colors`. In
+`sixteen` color mode, these are written with the old 16-color escape codes
+rather than the 256-color ones. */
+fn nick_styles(colors: &[u8], sixteen: bool) -> Vec<Style> {
+    colors.iter().map(|n| {
+        if sixteen {
+            Style::basic(*n)
+        } else {
+            Style::new(Some(style::Color::AnsiValue(*n)), None, None)
+        }
+    }).collect()
 }
 
 impl std::default::Default for Styles {
@@ -70,6 +151,19 @@ impl std::default::Default for Styles {
             bold: DEFAULT_BOLD.clone(),
             high: DEFAULT_HIGHLIGHT.clone(),
             high_bold: DEFAULT_HIGHLIGHT_BOLD.clone(),
+            announce: DEFAULT_ANNOUNCE.clone(),
+            search: DEFAULT_SEARCH.clone(),
+            mention: DEFAULT_MENTION.clone(),
+            nicks: DEFAULT_NICKS.clone(),
+            own: DEFAULT_PLAIN.clone(),
+            others: DEFAULT_PLAIN.clone(),
+            private: DEFAULT_PLAIN.clone(),
+            info: DEFAULT_PLAIN.clone(),
+            error: DEFAULT_DIM.clone(),
+            op: DEFAULT_DIM_BOLD.clone(),
+            status: DEFAULT_HIGHLIGHT.clone(),
+            separator: DEFAULT_DIM.clone(),
+            input: DEFAULT_PLAIN.clone(),
         }
     }
 }
@@ -90,16 +184,16 @@ impl Bits {
     fn new(sty: &Styles, width: u16) -> Bits {
         let mut start = Line::new();
         let mut end   = Line::new();
-        start.pushf(VBARSTR.as_str(), &sty.dim);
+        start.pushf(VBARSTR.as_str(), &sty.separator);
         start.push(" ");
         end.push(" ");
-        end.pushf(VBARSTR.as_str(), &sty.dim);
+        end.pushf(VBARSTR.as_str(), &sty.separator);
         
         let mut hline = Line::new();
         {
             let mut s = String::with_capacity(width as usize);
             for _ in 0..width { s.push(HBAR); }
-            hline.pushf(&s, &sty.dim);
+            hline.pushf(&s, &sty.separator);
         }
         
         let start_len = start.len();
@@ -115,11 +209,56 @@ impl Bits {
     }
 }
 
+/** A `Buffer` is one of the scrollbacks the `Screen` can show in its main
+window, along with how far it's scrolled and how much has been added to it
+since it was last shown.
+*/
+struct Buffer {
+    name: String,
+    lines: Vec<Line>,
+    scroll: u16,
+    /// index in `lines` of the current search match
+    search_at: Option<usize>,
+    /// lines added while not shown
+    unread: usize,
+    /// lines added while not shown that mention the user
+    mentions: usize,
+}
+
+impl Buffer {
+    fn new(name: &str) -> Buffer {
+        Buffer {
+            name: name.to_string(),
+            lines: Vec::new(),
+            scroll: 0,
+            search_at: None,
+            unread: 0,
+            mentions: 0,
+        }
+    }
+    
+    /* Trim the scrollback to the latest `n` lines. */
+    fn prune(&mut self, n: usize) {
+        if n >= self.lines.len() { return; }
+        let new_zero = self.lines.len() - n;
+        
+        let temp: Vec<Line> = self.lines.split_off(new_zero);
+        self.lines = temp;
+        self.search_at = self.search_at.and_then(|n| n.checked_sub(new_zero));
+    }
+}
+
 /** The `Screen` represents all the state required to display the `grel`
 client UI to the user.
+
+The main window shows one of a number of `Buffer`s at a time. There is
+always at least one; the `Screen` starts with a single `Buffer` called
+"status".
 */
 pub struct Screen {
-    lines: Vec<Line>,
+    bufs: Vec<Buffer>,
+    /// index of the `Buffer` being shown
+    cur: usize,
     input: Vec<char>,
     input_ip: u16,
     roster: Vec<Line>,
@@ -127,7 +266,6 @@ pub struct Screen {
     stat_ul: Line,
     stat_ur: Line,
     stat_ll: Line,
-    #[allow(dead_code)]
     stat_lr: Line,
     lines_dirty: bool,
     input_dirty: bool,
@@ -136,8 +274,11 @@ pub struct Screen {
     styles: Styles,
     bits: Bits,
     
-    lines_scroll: u16,
     roster_scroll: u16,
+    /// pattern being searched for in the scrollback (empty if none)
+    search: Vec<char>,
+    /// bell or notification escape to write at the next refresh
+    alert: Option<String>,
     last_x_size: u16,
     last_y_size: u16,
 }
@@ -157,13 +298,16 @@ impl Screen {
         let bitz   = Bits::new(&stylez, x);
         
         Ok(Screen {
-            lines: Vec::new(), input: Vec::new(), roster: Vec::new(),
+            bufs: vec![Buffer::new("status")], cur: 0,
+            input: Vec::new(), roster: Vec::new(),
             roster_width: roster_chars, input_ip: 0,
             stat_ul: Line::new(), stat_ur: Line::new(),
             stat_ll: Line::new(), stat_lr: Line::new(),
             lines_dirty: true,  input_dirty: true,
             roster_dirty: true, stat_dirty: true,
-            lines_scroll: 0, roster_scroll: 0,
+            roster_scroll: 0,
+            search: Vec::new(),
+            alert: None,
             last_x_size: x, last_y_size: y,
             styles: stylez,
             bits: bitz,
@@ -188,22 +332,10 @@ impl Screen {
         high_bg: Option<u8>,
         underline: bool,
     ) {
-        let dfg = match dim_fg {
-            None    => None,
-            Some(n) => Some(style::Color::AnsiValue(n)),
-        };
-        let dbg = match dim_bg {
-            None    => None,
-            Some(n) => Some(style::Color::AnsiValue(n)),
-        };
-        let hfg = match high_fg {
-            None    => None,
-            Some(n) => Some(style::Color::AnsiValue(n)),
-        };
-        let hbg = match high_bg {
-            None    => None,
-            Some(n) => Some(style::Color::AnsiValue(n)),
-        };
+        let dfg = dim_fg.map(style::Color::AnsiValue);
+        let dbg = dim_bg.map(style::Color::AnsiValue);
+        let hfg = high_fg.map(style::Color::AnsiValue);
+        let hbg = high_bg.map(style::Color::AnsiValue);
         let attr = match underline {
             true  => style::Attribute::Underlined,
             false => style::Attribute::Bold,
@@ -215,43 +347,148 @@ impl Screen {
             bold:       Style::new(None, None, Some(&[attr])),
             high:       Style::new(hfg, hbg, None),
             high_bold:  Style::new(hfg, hbg, Some(&[attr])),
+            announce:   Style::new(hfg, hbg, Some(&[attr, style::Attribute::Reverse])),
+            search:     Style::new(hfg, hbg, Some(&[style::Attribute::Reverse])),
+            mention:    Style::new(hfg, hbg, Some(&[attr, style::Attribute::Italic])),
+            nicks:      self.styles.nicks.clone(),
+            own:        DEFAULT_PLAIN.clone(),
+            others:     DEFAULT_PLAIN.clone(),
+            private:    DEFAULT_PLAIN.clone(),
+            info:       DEFAULT_PLAIN.clone(),
+            error:      Style::new(dfg, dbg, None),
+            op:         Style::new(dfg, dbg, Some(&[attr])),
+            status:     Style::new(hfg, hbg, None),
+            separator:  Style::new(dfg, dbg, None),
+            input:      DEFAULT_PLAIN.clone(),
         };
         
-        self.styles = new_styles;
+        self.use_styles(new_styles);
+    }
+    
+    /** Replace all the styles used by the `Screen` at once (as when
+    applying a theme).
+    */
+    pub fn use_styles(&mut self, styles: Styles) {
+        self.styles = styles;
         self.bits = Bits::new(&self.styles, self.last_x_size);
+        self.lines_dirty = true;
+        self.input_dirty = true;
+        self.roster_dirty = true;
+        self.stat_dirty = true;
+    }
+    
+    /** Ring the terminal bell (or write whatever other escape sequence
+    `seq` is, like a desktop notification) at the next refresh.
+    */
+    pub fn alert(&mut self, seq: &str) {
+        self.alert = Some(seq.to_string());
+    }
+    
+    /** Set the palette speakers' names are colored from: the ANSI color
+    numbers `colors`, or the default palette if `None`. An empty palette
+    turns name coloring off. With `sixteen` set, only the basic 16 colors
+    are used (see `nick_styles()`).
+    */
+    pub fn set_nick_colors(&mut self, colors: Option<&[u8]>, sixteen: bool) {
+        let colors = match colors {
+            Some(c) => c,
+            None if sixteen => NICK_COLORS_16,
+            None => NICK_COLORS,
+        };
+        self.styles.nicks = nick_styles(colors, sixteen);
     }
     
     /** Return the height of the main scrollback window. */
     pub fn get_main_height(&self) -> u16 { self.last_y_size - 2 }
     
-    /** Return the number of `Line`s in the scrollback buffer. */
-    pub fn get_scrollback_length(&self) -> usize { self.lines.len() }
+    /** Return the number of `Line`s in the longest scrollback buffer. */
+    pub fn get_scrollback_length(&self) -> usize {
+        self.bufs.iter().map(|b| b.lines.len()).max().unwrap_or(0)
+    }
     
-    /** Trim the scrollback buffer to the latest `n` lines. */
+    /** Trim each scrollback buffer to its latest `n` lines. */
     pub fn prune_scrollback(&mut self, n: usize) {
-        if n >= self.lines.len() { return; }
-        let new_zero = self.lines.len() - n;
-        
-        let temp: Vec<Line> = self.lines.split_off(new_zero);
-        self.lines = temp;
-        
+        for b in self.bufs.iter_mut() { b.prune(n); }
         self.lines_dirty = true;
     }
     
-    /** Push the supplied line onto the end of the scrollback buffer. */
+    /** Push the supplied line onto the end of the scrollback buffer being
+    shown. */
     pub fn push_line(&mut self, l: Line) {
-        self.lines.push(l);
+        self.bufs[self.cur].lines.push(l);
         self.lines_dirty = true;
     }
     
-    /** Populate the roster with the given slice of strings. */
-    pub fn set_roster<T: AsRef<str>>(&mut self, items: &[T]) {
-        self.roster = Vec::new();
-        for s in items.iter() {
-            let mut l: Line = Line::new();
-            l.push(s.as_ref());
-            self.roster.push(l);
+    /** Push the supplied line onto the end of buffer `n`. If that buffer
+    isn't being shown, this counts toward its unread lines (and mentions,
+    if `mention` is true).
+    */
+    pub fn push_line_to(&mut self, n: usize, l: Line, mention: bool) {
+        if n == self.cur {
+            self.push_line(l);
+            return;
+        }
+        if let Some(b) = self.bufs.get_mut(n) {
+            b.lines.push(l);
+            b.unread += 1;
+            if mention { b.mentions += 1; }
+        }
+    }
+    
+    /** Add a new (empty) buffer with the given name, returning its index. */
+    pub fn add_buffer(&mut self, name: &str) -> usize {
+        self.bufs.push(Buffer::new(name));
+        return self.bufs.len() - 1;
+    }
+    
+    /** Remove buffer `n`. (The last remaining buffer can't be removed.) If
+    it's the one being shown, the one that takes its place (or, if it was
+    the last, the new last one) is shown instead.
+    */
+    pub fn remove_buffer(&mut self, n: usize) {
+        if n >= self.bufs.len() || self.bufs.len() == 1 { return; }
+        self.bufs.remove(n);
+        if self.cur == n {
+            self.switch_buffer(n.min(self.bufs.len() - 1));
+        } else if self.cur > n {
+            self.cur -= 1;
         }
+    }
+    
+    /** Show buffer `n`, marking everything in it as read. */
+    pub fn switch_buffer(&mut self, n: usize) {
+        if n >= self.bufs.len() { return; }
+        self.cur = n;
+        self.bufs[n].unread = 0;
+        self.bufs[n].mentions = 0;
+        self.lines_dirty = true;
+    }
+    
+    /** Return the index of the buffer being shown. */
+    pub fn current_buffer(&self) -> usize { self.cur }
+    
+    /** Return the number of buffers. */
+    pub fn buffer_count(&self) -> usize { self.bufs.len() }
+    
+    /** Return the name of buffer `n`. */
+    pub fn buffer_name(&self, n: usize) -> &str {
+        self.bufs.get(n).map(|b| b.name.as_str()).unwrap_or("")
+    }
+    
+    /** Rename buffer `n`. */
+    pub fn set_buffer_name(&mut self, n: usize, name: &str) {
+        if let Some(b) = self.bufs.get_mut(n) { b.name = name.to_string(); }
+    }
+    
+    /** Return the numbers of unread lines and of unread mentions in
+    buffer `n`. */
+    pub fn buffer_activity(&self, n: usize) -> (usize, usize) {
+        self.bufs.get(n).map(|b| (b.unread, b.mentions)).unwrap_or((0, 0))
+    }
+    
+    /** Populate the roster with the given `Line`s. */
+    pub fn set_roster(&mut self, items: Vec<Line>) {
+        self.roster = items;
         self.roster_dirty = true;
     }
     
@@ -381,8 +618,8 @@ impl Screen {
         
         self.input_dirty = true;
         let mut in_ws = false;
-        if uip < self.input.len() {
-            if self.input[uip-1].is_whitespace() { in_ws = true; }
+        if uip < self.input.len() && self.input[uip-1].is_whitespace() {
+            in_ws = true;
         }
         
         for (i, c) in self.input[..uip].iter().rev().enumerate() {
@@ -402,13 +639,67 @@ impl Screen {
     or to the end (or beginning) if the new position would be out of range.
     */
     pub fn scroll_lines(&mut self, n_chars: i16) {
-        let cur = self.lines_scroll as i16;
+        let buf = &mut self.bufs[self.cur];
+        let cur = buf.scroll as i16;
         let mut new = cur + n_chars;
         if new < 0 { new = 0; }
-        self.lines_scroll = new as u16;
+        buf.scroll = new as u16;
+        self.lines_dirty = true;
+    }
+    
+    /** Set (or, with `None`, clear) the pattern to highlight in the
+    scrollback, and forget the current match.
+    */
+    pub fn set_search(&mut self, pat: Option<&str>) {
+        self.search = match pat {
+            Some(s) => s.chars().collect(),
+            None => Vec::new(),
+        };
+        for b in self.bufs.iter_mut() { b.search_at = None; }
         self.lines_dirty = true;
     }
     
+    /** Find the next `Line` in the scrollback that matches the search
+    pattern: older than the current match if `backward`, newer if not
+    (wrapping around the end of the scrollback either way), and scroll
+    the main display so it's in the middle.
+    
+    Returns `false` if nothing matches.
+    */
+    pub fn search_lines(&mut self, backward: bool) -> bool {
+        let width = self.last_x_size.saturating_sub(self.roster_width + 1) as usize;
+        let half = (self.get_main_height().saturating_sub(1) / 2) as usize;
+        let buf = &mut self.bufs[self.cur];
+        let n = buf.lines.len();
+        if self.search.len() == 0 || n == 0 { return false; }
+        /* With no current match, start from just past the newest line. */
+        let start = buf.search_at.unwrap_or(n);
+        
+        let mut found: Option<usize> = None;
+        for k in 1..=(n + 1) {
+            let i = match backward {
+                true  => (start + n + 1 - k) % (n + 1),
+                false => (start + k) % (n + 1),
+            };
+            if i < n && buf.lines[i].find(&self.search).len() > 0 {
+                found = Some(i);
+                break;
+            }
+        }
+        let i = match found {
+            Some(i) => i,
+            None => { return false; },
+        };
+        buf.search_at = Some(i);
+        
+        if width == 0 { return true; }
+        let below: usize = buf.lines[(i+1)..].iter_mut()
+            .map(|l| l.lines(width).len()).sum();
+        buf.scroll = below.saturating_sub(half) as u16;
+        self.lines_dirty = true;
+        return true;
+    }
+    
     /** Scroll the roster up (or down, for negative values) `n_chars`,
     or to the end (or beginning) if the new position would be out of range.
     */
@@ -433,6 +724,20 @@ impl Screen {
         }
     }
     
+    /** Return the contents of the input line. */
+    pub fn get_input(&self) -> &[char] { &self.input }
+    
+    /** Return the position of the cursor in the input line. */
+    pub fn get_input_ip(&self) -> usize { self.input_ip as usize }
+    
+    /** Replace the contents of the input line, putting the cursor at
+    the end. */
+    pub fn set_input(&mut self, s: &str) {
+        self.input = s.chars().collect();
+        self.input_ip = self.input.len() as u16;
+        self.input_dirty = true;
+    }
+    
     /** Return the contents of the input line as a String and clear
     the input line.
     */
@@ -456,6 +761,10 @@ impl Screen {
         self.stat_ur = new_stat;
         self.stat_dirty = true;
     }
+    pub fn set_stat_lr(&mut self, new_stat: Line) {
+        self.stat_lr = new_stat;
+        self.stat_dirty = true;
+    }
     
     /** Set the size at which the `Screen` should be rendered. This is
     intended to be the entire terminal window.
@@ -468,7 +777,7 @@ impl Screen {
             let mut s = String::with_capacity(cols as usize);
             for _ in 0..cols { s.push(HBAR); }
             let mut hl = Line::new();
-            hl.pushf(&s, &self.styles.dim);
+            hl.pushf(&s, &self.styles.separator);
             self.bits.full_hline = hl.first_n_chars(cols as usize).to_string();
         }
         if (cols != self.last_x_size) || (rows != self.last_y_size) {
@@ -497,10 +806,19 @@ impl Screen {
         let mut y = height - 1;
         let w = width as usize;
         let mut count_back: u16 = 0;
-        for aline in self.lines.iter_mut().rev() {
-            for row in aline.lines(w).iter().rev() {
+        let scroll = self.bufs[self.cur].scroll;
+        for aline in self.bufs[self.cur].lines.iter_mut().rev() {
+            let starts = aline.find(&self.search);
+            let mut hl: Line;
+            let rows = if starts.len() > 0 {
+                hl = aline.highlighted(&starts, self.search.len(), &self.styles.search);
+                hl.lines(w)
+            } else {
+                aline.lines(w)
+            };
+            for row in rows.iter().rev() {
                 if y == 0 { break; }
-                if count_back >= self.lines_scroll {
+                if count_back >= scroll {
                     term.queue(cursor::MoveTo(0, y))?
                         .queue(style::Print(&blank))?
                         .queue(cursor::MoveToColumn(0))?
@@ -515,7 +833,7 @@ impl Screen {
         /* Check to see if we've scrolled past the end of the scrollback,
         and if so, scroll us forward a little bit and keep
         `self.lines_dirty == true` */
-        if y > 1 && self.lines_scroll > 0 {
+        if y > 1 && scroll > 0 {
             let adjust: i16 = (y - 1) as i16;
             self.scroll_lines(-adjust);
         } else {
@@ -544,7 +862,7 @@ impl Screen {
             let mut s = String::new();
             for _ in 0..self.roster_width { s.push(SPACE); }
             let mut l = Line::new();
-            l.pushf(VBARSTR.as_str(), &self.styles.dim);
+            l.pushf(VBARSTR.as_str(), &self.styles.separator);
             l.push(&s);
             l.first_n_chars(rrw).to_string()
         };
@@ -571,16 +889,16 @@ impl Screen {
     }
     
     fn refresh_input(&mut self, term: &mut Stdout) -> crossterm::Result<()> {
+        /* The input style goes first, so a background color fills the line. */
         term.queue(cursor::MoveTo(0, self.last_y_size - 1))?
+            .queue(style::Print(&*self.styles.input))?
             .queue(terminal::Clear(terminal::ClearType::CurrentLine))?
             .queue(cursor::MoveToColumn(0))?;
         
         let third = self.last_x_size / 3;
         let maxpos = self.last_x_size - third;
         let startpos = {
-            if self.input.len() < self.last_x_size as usize {
-                0
-            } else if self.input_ip < third {
+            if self.input.len() < self.last_x_size as usize || self.input_ip < third {
                 0
             } else if self.input_ip > maxpos {
                 self.input_ip - maxpos
@@ -604,7 +922,9 @@ impl Screen {
             let c = self.input[i];
             if i == input_ip_us {
                 let cch = style::style(c).attribute(style::Attribute::Reverse);
-                term.queue(style::PrintStyledContent(cch))?;
+                /* Printing the cursor resets the style. */
+                term.queue(style::PrintStyledContent(cch))?
+                    .queue(style::Print(&*self.styles.input))?;
             } else {
                 term.queue(style::Print(c))?;
             }
@@ -613,6 +933,7 @@ impl Screen {
             let cch = style::style(SPACE).attribute(style::Attribute::Reverse);
             term.queue(style::PrintStyledContent(cch))?;
         }
+        term.queue(style::Print(&**RESET_ALL))?;
         
         self.input_dirty = false;
         Ok(())
@@ -621,7 +942,7 @@ impl Screen {
     fn refresh_stat(&mut self, term: &mut Stdout) -> crossterm::Result<()> {
         trace!("Screen::refresh_stat(...) called");
         
-        /* Lower left corner (there is no lower-right as of yet). */
+        /* Lower left corner. */
         let stat_pad = 2 + self.bits.stat_begin_chars + self.bits.stat_end_chars;
         let stat_room = (self.last_x_size as usize) - stat_pad;
         let ll_y = self.last_y_size - 2;
@@ -633,6 +954,18 @@ impl Screen {
             .queue(style::Print(self.stat_ll.first_n_chars(stat_room)))?
             .queue(style::Print(&self.bits.stat_end))?;
         
+        /* Lower right corner, in whatever space the lower left leaves. */
+        let ll_used = stat_pad + self.stat_ll.len().min(stat_room);
+        let lr_room = (self.last_x_size as usize).saturating_sub(ll_used + stat_pad);
+        let lr_len = self.stat_lr.len().min(lr_room);
+        if lr_len > 0 {
+            let lr_x = (self.last_x_size as usize) - (stat_pad - 1 + lr_len);
+            term.queue(cursor::MoveTo(lr_x as u16, ll_y))?
+                .queue(style::Print(&self.bits.stat_begin))?
+                .queue(style::Print(self.stat_lr.first_n_chars(lr_len)))?
+                .queue(style::Print(&self.bits.stat_end))?;
+        }
+        
         /* Upper left and right corners. */
         
         /* characters surrounding actual text:
@@ -701,6 +1034,14 @@ impl Screen {
     */
     pub fn refresh(&mut self, term: &mut Stdout) -> Result<(), String>{
         //trace!("Screen::refresh(...) called");
+        if let Some(seq) = self.alert.take() {
+            if let Err(e) = term.queue(style::Print(seq)) {
+                return Err(format!("{}", e));
+            }
+            if let Err(e) = term.flush() {
+                return Err(format!("{}", e));
+            }
+        }
         if !(self.lines_dirty || self.input_dirty  ||
                                  self.roster_dirty || self.stat_dirty) {
             return Ok(());
@@ -14,6 +14,8 @@ it gets encoded when sticking it into a `proto3::Env`, and those bytes
 get pushed into the `Sock`.
 
 */
+/* `cfg(will_never_be_used)` switches off the stale tests at the bottom. */
+#![allow(unexpected_cfgs)]
 use std::io::{Read, Write};
 use std::net::{TcpStream, Shutdown};
 use std::error::Error;
@@ -22,9 +24,9 @@ use super::proto3::Rcvr;
 
 const DEFAULT_BUFFER_SIZE: usize = 1024;
 
-const NEWLINE: u8 = '\n' as u8;
+const NEWLINE: u8 = b'\n';
 
-static ERRS: &'static [&'static str] = &[
+static ERRS: &[&str] = &[
     "Unable to set_nodelay on underlying socket",               // 0
     "Unable to set_nonblocking on underlying socket",           // 1
     "Error shutting down underlying socket",                    // 2
@@ -93,10 +95,10 @@ fn get_actual_offset(dat: &[u8], e: &serde_json::Error)
             match dat.get(n) {
                 None => break,
                 Some(b) => {
-                    if *b == NEWLINE { line_n = line_n + 1; }
+                    if *b == NEWLINE { line_n += 1; }
                 },
             }
-            n = n + 1;
+            n += 1;
         } else {
             offs = Some(n + col);
             break;
@@ -138,8 +140,7 @@ impl Sock {
         if let Err(e) = stream.set_nonblocking(true) {
             return Err(SockError::from_err(1, &e));
         }
-        let mut new_buff: Vec<u8> = Vec::with_capacity(DEFAULT_BUFFER_SIZE);
-        new_buff.resize(DEFAULT_BUFFER_SIZE, 0u8);
+        let new_buff: Vec<u8> = vec![0u8; DEFAULT_BUFFER_SIZE];
         let s = Sock {
             sock: stream,
             read_buff: new_buff,
@@ -223,7 +224,7 @@ impl Sock {
         let maybe_msg = serde_json::from_slice::<Rcvr>(&self.current[..offs]);
         match maybe_msg {
             Ok(m) => {
-                let temp = (&self.current[offs..]).to_vec();
+                let temp = self.current[offs..].to_vec();
                 self.current = temp;
                 return Ok(Some(m));
             },
@@ -285,7 +286,7 @@ impl Sock {
                         Ok(0)
                     }
                 } else {
-                    let temp = (&self.send_buff[n..]).to_vec();
+                    let temp = self.send_buff[n..].to_vec();
                     self.send_buff = temp;
                     Ok(self.send_buff.len())
                 }
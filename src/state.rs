@@ -0,0 +1,240 @@
+/*!
+Saving and restoring the durable parts of the server's state.
+
+`greld` periodically (and when it shuts down) writes a "snapshot" of
+everything that should survive a restart to a single JSON file, and reads
+it back in when it starts. The top level of the file looks like this:
+
+``` ignore
+{
+  "version": 2,            // see `VERSION`, below
+  "saved": 1612345678,     // when the snapshot was written (Unix seconds)
+  "bans": [ ... ],         // server-wide address bans (`BanState`)
+  "accounts": [ ... ],     // registered names (`Account`)
+  "rooms": [ ... ],        // rooms and their settings (`RoomState`)
+  "memos": [ ... ]         // undelivered memos (`Memo`)
+}
+```
+
+All times are in seconds since the Unix epoch. User IDs mean nothing
+from one run of the server to the next, so everything that refers to a
+`User` does so by name: bans and invitations by the `User`'s name at the
+time, and `Room` ranks and block lists by registered name (ranks and
+blocks belonging to unregistered `User`s can't be restored, so they
+aren't saved).
+*/
+use serde::{Deserialize, Serialize};
+
+use super::room::Role;
+
+/** The version of the snapshot format written by this version of the
+server. Bump this whenever the format changes in a way older servers
+can't read, and teach `Snapshot::from_json()` to upgrade the old version.
+
+  * 1: the original format
+  * 2: adds `memos`; version 1 files are read as having none
+*/
+pub const VERSION: u32 = 2;
+
+/** A record of a `Room` ban or invitation (see `room::Mark`). */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MarkState {
+    pub name: String,
+    pub by: String,
+    pub when: u64,
+}
+
+/** A registered name held by an account, and the rank it holds. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RankState {
+    /// collapsed registered name
+    pub account: String,
+    pub role: Role,
+}
+
+/** Everything worth remembering about a `Room`. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RoomState {
+    pub name: String,
+    pub closed: bool,
+    pub topic: String,
+    /// hashed, as by `passwd::hash()`
+    pub key: Option<String>,
+    pub limit: Option<usize>,
+    /// slow mode interval, in seconds
+    pub slow: Option<u64>,
+    pub ranks: Vec<RankState>,
+    pub bans: Vec<MarkState>,
+    pub invites: Vec<MarkState>,
+}
+
+impl RoomState {
+    /** Return whether the `Room`'s owner is a registered name. Such rooms
+    are kept around while empty so their owners can come back to them. */
+    pub fn has_owner(&self) -> bool {
+        self.ranks.iter().any(|r| r.role == Role::Owner)
+    }
+}
+
+/** A server-wide ban of an address. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BanState {
+    /// IP address, without port
+    pub addr: String,
+    /// name of the `User` banned
+    pub name: String,
+    /// administrator account that set the ban
+    pub by: String,
+    pub when: u64,
+}
+
+/** A registered name. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    /// the name as registered (not collapsed)
+    pub name: String,
+    /// hashed, as by `passwd::hash()`
+    pub password: String,
+    pub registered: u64,
+    /// collapsed names of the `User`s this account blocks
+    pub blocks: Vec<String>,
+}
+
+/** A message left for a `User` who wasn't logged on. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Memo {
+    /// name of the sender
+    pub from: String,
+    /// collapsed name of the recipient
+    pub to: String,
+    pub text: String,
+    pub sent: u64,
+}
+
+/** The whole of the saved state. */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub saved: u64,
+    pub bans: Vec<BanState>,
+    pub accounts: Vec<Account>,
+    pub rooms: Vec<RoomState>,
+    #[serde(default)]
+    pub memos: Vec<Memo>,
+}
+
+impl Snapshot {
+    /** Read a `Snapshot` from the file at `path`. Returns `Ok(None)` if
+    there is no such file (as on a server's first run).
+    */
+    pub fn load(path: &str) -> Result<Option<Snapshot>, String> {
+        let s = match std::fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => { return Ok(None); },
+            Err(e) => { return Err(format!("Unable to read {:?}: {}", path, &e)); },
+        };
+        Snapshot::from_json(&s).map(Some)
+    }
+    
+    /** Decode a `Snapshot` from JSON, refusing versions this server
+    doesn't understand. */
+    pub fn from_json(s: &str) -> Result<Snapshot, String> {
+        #[derive(Deserialize)]
+        struct Versioned { version: u32, }
+        
+        let v: Versioned = match serde_json::from_str(s) {
+            Ok(v) => v,
+            Err(e) => { return Err(format!("Not a saved state file: {}", &e)); },
+        };
+        if v.version == 0 || v.version > VERSION {
+            return Err(format!("Unsupported saved state version {} (expected {}).",
+                               v.version, VERSION));
+        }
+        /* Older versions differ only in lacking fields that default
+        sensibly, so there's no more upgrading to do than this. */
+        match serde_json::from_str::<Snapshot>(s) {
+            Ok(mut snap) => {
+                snap.version = VERSION;
+                Ok(snap)
+            },
+            Err(e) => Err(format!("Error parsing saved state: {}", &e)),
+        }
+    }
+    
+    /** Write the `Snapshot` to the file at `path`. It's written to a
+    temporary file first and then moved into place, so a crash mid-write
+    won't destroy the previous snapshot.
+    */
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let json = match serde_json::to_string_pretty(self) {
+            Ok(j) => j,
+            Err(e) => { return Err(format!("Unable to encode saved state: {}", &e)); },
+        };
+        let tmp = format!("{}.tmp", path);
+        if let Err(e) = std::fs::write(&tmp, json.as_bytes()) {
+            return Err(format!("Unable to write {:?}: {}", &tmp, &e));
+        }
+        if let Err(e) = std::fs::rename(&tmp, path) {
+            return Err(format!("Unable to move {:?} to {:?}: {}", &tmp, path, &e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    #[test]
+    fn round_trip() {
+        let snap = Snapshot {
+            version: VERSION,
+            saved: 1612345678,
+            bans: vec![BanState {
+                addr: "10.0.0.7".to_string(),
+                name: "Fps Doug".to_string(),
+                by: "root".to_string(),
+                when: 1612345000,
+            }],
+            accounts: vec![Account {
+                name: "Naggum".to_string(),
+                password: "$argon2i$...".to_string(),
+                registered: 1612340000,
+                blocks: vec!["fpsdoug".to_string()],
+            }],
+            rooms: vec![RoomState {
+                name: "The Den".to_string(),
+                closed: true,
+                topic: "XML".to_string(),
+                key: None,
+                limit: Some(12),
+                slow: None,
+                ranks: vec![RankState { account: "naggum".to_string(), role: Role::Owner }],
+                bans: vec![],
+                invites: vec![MarkState {
+                    name: "Guy Steele".to_string(),
+                    by: "Naggum".to_string(),
+                    when: 1612341000,
+                }],
+            }],
+            memos: vec![Memo {
+                from: "Guy Steele".to_string(),
+                to: "naggum".to_string(),
+                text: "Lisp is still the best.".to_string(),
+                sent: 1612342000,
+            }],
+        };
+        let json = serde_json::to_string(&snap).unwrap();
+        assert_eq!(Snapshot::from_json(&json).unwrap(), snap);
+        assert!(snap.rooms[0].has_owner());
+        
+        let future = json.replacen("\"version\":2", "\"version\":99", 1);
+        assert!(Snapshot::from_json(&future).is_err());
+        
+        let v1 = r#"{"version":1,"saved":0,"bans":[],"accounts":[],"rooms":[]}"#;
+        let old = Snapshot::from_json(v1).unwrap();
+        assert_eq!(old.version, VERSION);
+        assert!(old.memos.is_empty());
+        assert!(Snapshot::from_json("{}").is_err());
+    }
+}
@@ -0,0 +1,249 @@
+/*!
+Themes for the client's screen.
+
+A theme is a `.toml` file giving the style of any of the elements the
+`Screen` draws (each a field of `screen::Styles`), like
+
+```toml
+nick_colors = [31, "dark_cyan", "#e5c07b"]
+
+[private]
+fg = "#c678dd"
+italic = true
+
+[status]
+fg = 15
+bg = "dark_blue"
+```
+
+Colors can be ANSI color numbers (0-255), `crossterm`'s color names
+("red", "dark_red", "grey", &c.), "reset" (the terminal's own color), or
+"#rrggbb" for a truecolor RGB value; the attributes `bold`, `italic`,
+`underline`, and `reverse` can be turned on. Elements a theme doesn't
+mention keep whatever style they had.
+
+Two themes, "dark" and "light", are built in.
+*/
+use std::convert::TryFrom;
+
+use crossterm::style::{Attribute, Color};
+
+use crate::line::Style;
+use crate::screen::Styles;
+
+const DARK: &str = include_str!("../themes/dark.toml");
+const LIGHT: &str = include_str!("../themes/light.toml");
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum ColorSpec {
+    Ansi(u8),
+    Name(String),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ElementSpec {
+    fg: Option<ColorSpec>,
+    bg: Option<ColorSpec>,
+    bold: Option<bool>,
+    italic: Option<bool>,
+    underline: Option<bool>,
+    reverse: Option<bool>,
+}
+
+fn parse_color(spec: &ColorSpec) -> Result<Color, String> {
+    let s = match spec {
+        ColorSpec::Ansi(n) => { return Ok(Color::AnsiValue(*n)); },
+        ColorSpec::Name(s) => s,
+    };
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() == 6 {
+            if let Ok(n) = u32::from_str_radix(hex, 16) {
+                return Ok(Color::Rgb {
+                    r: (n >> 16) as u8,
+                    g: (n >> 8) as u8,
+                    b: n as u8,
+                });
+            }
+        }
+        return Err(format!("{:?} isn't a color; RGB colors look like \"#e5c07b\".", s));
+    }
+    if s.to_lowercase() == "reset" { return Ok(Color::Reset); }
+    match Color::try_from(s.as_str()) {
+        Ok(c) => Ok(c),
+        Err(_) => Err(format!("{:?} isn't a color.", s)),
+    }
+}
+
+fn parse_element(spec: &ElementSpec) -> Result<Style, String> {
+    let fg = match &spec.fg {
+        None => None,
+        Some(c) => Some(parse_color(c)?),
+    };
+    let bg = match &spec.bg {
+        None => None,
+        Some(c) => Some(parse_color(c)?),
+    };
+    let mut attrs: Vec<Attribute> = Vec::new();
+    if spec.bold == Some(true) { attrs.push(Attribute::Bold); }
+    if spec.italic == Some(true) { attrs.push(Attribute::Italic); }
+    if spec.underline == Some(true) { attrs.push(Attribute::Underlined); }
+    if spec.reverse == Some(true) { attrs.push(Attribute::Reverse); }
+    
+    return Ok(Style::new(fg, bg, Some(&attrs)));
+}
+
+/* Each theme element is a stanza of the theme file and a field of
+`Styles`; this declares them all in one place. */
+macro_rules! elements {
+    ($($name:ident => $field:ident),* $(,)?) => {
+        #[derive(serde::Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct ThemeFile {
+            nick_colors: Option<Vec<ColorSpec>>,
+            $($name: Option<ElementSpec>,)*
+        }
+        
+        /* The styles a theme gives its elements. */
+        #[derive(Default)]
+        struct Elements {
+            $($name: Option<Style>,)*
+        }
+        
+        impl Elements {
+            fn parse(f: &ThemeFile) -> Result<Elements, String> {
+                let mut e = Elements::default();
+                $(if let Some(spec) = &f.$name {
+                    match parse_element(spec) {
+                        Ok(s) => { e.$name = Some(s); },
+                        Err(err) => { return Err(format!("[{}]: {}", stringify!($name), &err)); },
+                    }
+                })*
+                Ok(e)
+            }
+            
+            fn apply(&self, sty: &mut Styles) {
+                $(if let Some(s) = &self.$name { sty.$field = s.clone(); })*
+            }
+            
+            /* The names of the elements given. */
+            fn names(&self) -> Vec<&'static str> {
+                let mut v: Vec<&'static str> = Vec::new();
+                $(if self.$name.is_some() { v.push(stringify!($name)); })*
+                v
+            }
+        }
+    };
+}
+
+elements! {
+    dim => dim,
+    dim_bold => dim_bold,
+    bold => bold,
+    highlight => high,
+    highlight_bold => high_bold,
+    announce => announce,
+    search => search,
+    mention => mention,
+    own => own,
+    others => others,
+    private => private,
+    info => info,
+    error => error,
+    op => op,
+    status => status,
+    separator => separator,
+    input => input,
+}
+
+/** A parsed theme, ready to be applied to a `Styles`. */
+pub struct Theme {
+    nicks: Option<Vec<Style>>,
+    elements: Elements,
+}
+
+impl Theme {
+    /** Return the built-in theme called `name` ("dark" or "light"), if
+    there is one. */
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let src = match name {
+            "dark" => DARK,
+            "light" => LIGHT,
+            _ => { return None; },
+        };
+        return Some(Theme::from_toml(src).unwrap());
+    }
+    
+    /** Parse a theme from the contents of a theme file. */
+    pub fn from_toml(src: &str) -> Result<Theme, String> {
+        let f: ThemeFile = match toml::from_str(src) {
+            Ok(f) => f,
+            Err(e) => { return Err(format!("{}", &e)); },
+        };
+        
+        let nicks = match &f.nick_colors {
+            None => None,
+            Some(v) => {
+                let mut nicks: Vec<Style> = Vec::with_capacity(v.len());
+                for c in v.iter() {
+                    nicks.push(Style::new(Some(parse_color(c)?), None, None));
+                }
+                Some(nicks)
+            },
+        };
+        let elements = Elements::parse(&f)?;
+        
+        Ok(Theme { nicks, elements })
+    }
+    
+    /** Set the styles of the elements the theme gives in `sty`, and, if
+    `nicks` is set, its colors for speakers' names. (The client passes
+    `false` when the user has asked for only the basic sixteen colors,
+    which a theme's name colors may not stick to.)
+    */
+    pub fn apply(&self, sty: &mut Styles, nicks: bool) {
+        if let (true, Some(v)) = (nicks, &self.nicks) {
+            sty.nicks = v.clone();
+        }
+        self.elements.apply(sty);
+    }
+}
+
+impl std::fmt::Debug for Theme {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Theme {{ nick_colors: {}, elements: {:?} }}",
+               self.nicks.is_some(), &self.elements.names())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    
+    #[test]
+    fn parse_themes() {
+        for name in ["dark", "light"].iter() {
+            let t = Theme::builtin(name).unwrap();
+            assert_eq!(t.elements.names().len(), 17);
+            let mut sty = Styles::default();
+            t.apply(&mut sty, true);
+        }
+        assert!(Theme::builtin("plaid").is_none());
+        
+        let t = Theme::from_toml("nick_colors = [1, 2]\n[op]\nfg = 'dark_cyan'\n[status]\nbg = '#102030'\nbold = true\n").unwrap();
+        assert_eq!(t.elements.names(), vec!["op", "status"]);
+        let mut sty = Styles::default();
+        let before = sty.nicks.len();
+        t.apply(&mut sty, false);
+        assert_eq!(sty.nicks.len(), before);
+        assert!(sty.status.contains("48;2;16;32;48"));
+        t.apply(&mut sty, true);
+        assert_eq!(sty.nicks.len(), 2);
+        
+        assert!(Theme::from_toml("[op]\nfg = 'plaid'\n").is_err());
+        assert!(Theme::from_toml("[op]\nfg = '#12345'\n").is_err());
+        assert!(Theme::from_toml("[roster]\nfg = 3\n").is_err());
+        assert!(Theme::from_toml("[op]\nblink = true\n").is_err());
+    }
+}
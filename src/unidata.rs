@@ -620,14 +620,14 @@ pub fn generate_hash<'a>() -> HashMap<u32, Multichar<'a>> {
     
     while n < DATA_LENGTH {
         let c = RAW_DATA[n];
-        n = n + 1;
+        n += 1;
         let l: usize = RAW_DATA[n] as usize;
-        n = n + 1;
+        n += 1;
         map.insert(c, Multichar {
                         base: RAW_DATA[n],
                         rest: &RAW_DATA[(n+1)..(n+l)]
                       });
-        n = n + l;
+        n += l;
     }
     
     map
@@ -4,9 +4,11 @@ The `User` struct--representing a connected client--and related methods.
 updated: 2021-02-01
 
 */
+/* `cfg(debug)` (set by hand with `--cfg debug`) turns on drop logging. */
+#![allow(unexpected_cfgs)]
 
 use std::fmt::Display;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 use lazy_static::lazy_static;
 use super::sock::{Sock, SockError};
 //use super::proto2::{Endpoint, Env, Msg};
@@ -74,6 +76,11 @@ pub struct User {
     last_data_time: Instant,
     errs: Vec<SockError>,
     blocks: Vec<u64>,
+    admin: Option<String>,
+    account: Option<String>,
+    away: Option<String>,
+    connected: SystemTime,
+    last_text: Option<SystemTime>,
 }
 
 impl User {
@@ -89,6 +96,11 @@ impl User {
             last_data_time: Instant::now(),
             errs: Vec::<SockError>::new(),
             blocks: Vec::<u64>::new(),
+            admin: None,
+            account: None,
+            away: None,
+            connected: SystemTime::now(),
+            last_text: None,
         }
     }
     
@@ -110,6 +122,23 @@ impl User {
         self.idstr = ascollapse(new_name);
     }
     
+    /** Returns the name of the server administrator account this `User`
+    has authenticated as, if any.
+    */
+    pub fn get_admin(&self) -> Option<&str> { self.admin.as_deref() }
+    pub fn set_admin(&mut self, account: &str) {
+        self.admin = Some(String::from(account));
+    }
+    pub fn clear_admin(&mut self) { self.admin = None; }
+    
+    /** Returns the (collapsed) registered name this `User` has identified
+    as, if any.
+    */
+    pub fn get_account(&self) -> Option<&str> { self.account.as_deref() }
+    pub fn set_account(&mut self, account: &str) {
+        self.account = Some(String::from(account));
+    }
+    
     /** To implement throttling, the `User` increments and internal byte
     counter whenever certain types of `Msg`s are decoded from the underlying
     socket; this count can be lowered over time.
@@ -156,6 +185,35 @@ impl User {
         let _ = self.thesock.shutdown();
     }
     
+    /** Returns the number of bytes in the outgoing buffer still waiting
+    to be written to the underlying socket.
+    */
+    pub fn pending_bytes(&self) -> usize { self.thesock.send_buff_size() }
+    
+    /** Close the underlying socket without sending anything further. (To
+    say goodbye first, use `.logout()`.)
+    */
+    pub fn close(&mut self) {
+        let _ = self.thesock.shutdown();
+    }
+    
+    /** Returns this `User`'s away message, if he or she is away. */
+    pub fn get_away(&self) -> Option<&str> { self.away.as_deref() }
+    /** Set (or with `None`, clear) this `User`'s away message. */
+    pub fn set_away(&mut self, msg: Option<&str>) {
+        self.away = msg.map(String::from);
+    }
+    
+    /** Returns when this `User` connected. */
+    pub fn get_connected(&self) -> SystemTime { self.connected }
+    /** Returns when this `User` last sent `Text` (if ever). */
+    pub fn get_last_text(&self) -> Option<SystemTime> { self.last_text }
+    /** Record that this `User` has just sent `Text`. */
+    pub fn mark_text(&mut self) { self.last_text = Some(SystemTime::now()); }
+    
+    /** Return the IDs of the users this user has blocked. */
+    pub fn get_blocks(&self) -> &[u64] { &(self.blocks) }
+    
     /** Add the ID of a user to the list of users this user has blocked.
     Returns true if the ID was added and false if that ID was already blocked.
     */
@@ -255,7 +313,7 @@ impl User {
                 self.errs.push(e);
                 return None;
             },
-            Ok(n) => { self.bytes_sucked = self.bytes_sucked + n; },
+            Ok(n) => { self.bytes_sucked += n; },
         }
         
         let n_buff = self.thesock.recv_buff_size();
@@ -317,9 +375,8 @@ impl User {
                                 self.errs.push(e);
                                 return Err(err);
                             },
-                            Ok(opt) => match opt {
-                                Some(m) => { return Ok(m); }
-                                None => {},
+                            Ok(opt) => if let Some(m) = opt {
+                                return Ok(m);
                             },
                         }
                     }